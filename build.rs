@@ -1,13 +1,25 @@
-use std::fs;
-use std::path::PathBuf;
-use zkvm_interface::Compiler;
-
 fn main() {
+    // The other backends have no build step
+    #[cfg(feature = "sp1")]
+    if std::env::var_os("POOST_SKIP_GUEST_BUILD").is_none() {
+        compile_sp1();
+    }
+
+    println!("cargo:rerun-if-changed=programs");
+    // Shared with the guest
+    println!("cargo:rerun-if-changed=crates/validation");
+    println!("cargo:rerun-if-env-changed=POOST_SKIP_GUEST_BUILD");
+}
+
+#[cfg(feature = "sp1")]
+fn compile_sp1() {
+    use std::fs;
+    use std::path::PathBuf;
+    use zkvm_interface::Compiler;
+
     let program_dir = PathBuf::from("programs/sp1");
     let program = ere_sp1::RV32_IM_SUCCINCT_ZKVM_ELF::compile(&program_dir)
         .expect("Failed to compile SP1 program");
     // Write the ELF to a file so rust-embed can embed it
     fs::write(program_dir.join("sp1-program.elf"), program).expect("Failed to write ELF file");
-
-    println!("cargo:rerun-if-changed=programs");
 }
@@ -0,0 +1,166 @@
+//! Checks, error codes and profile region names shared by the host and the guest programs, and
+//! with the `profile` feature, the validation guests run in profile mode.
+//!
+//! The host validates blocks natively to predict the outcome a guest commits, so both sides
+//! must reject the same blocks with the same codes. The codes are part of the API: a variant keeps
+//! its code when it is renamed, and a new variant gets a new one.
+
+use alloy_consensus::Header;
+use alloy_primitives::B256;
+use alloy_rlp::Decodable;
+use core::fmt;
+use reth_stateless::validation::StatelessValidationError;
+use reth_stateless::ClientInput;
+
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod regions;
+
+/// An error with a stable snake case code, reported as the `code` of an invalid block
+pub trait ReasonCode {
+    fn code(&self) -> &'static str;
+}
+
+/// Why a block of a range is invalid
+#[derive(Debug)]
+pub enum BlockError {
+    /// The block does not continue the previous block of the range
+    Continuity(ContinuityError),
+    /// `stateless_validation` rejected the block
+    Validation(StatelessValidationError),
+}
+
+impl ReasonCode for BlockError {
+    fn code(&self) -> &'static str {
+        match self {
+            BlockError::Continuity(e) => e.code(),
+            BlockError::Validation(e) => e.code(),
+        }
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Continuity(e) => e.fmt(f),
+            BlockError::Validation(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<ContinuityError> for BlockError {
+    fn from(e: ContinuityError) -> Self {
+        BlockError::Continuity(e)
+    }
+}
+
+impl From<StatelessValidationError> for BlockError {
+    fn from(e: StatelessValidationError) -> Self {
+        BlockError::Validation(e)
+    }
+}
+
+/// A block of a range that does not continue the previous one
+#[derive(Debug)]
+pub enum ContinuityError {
+    ParentHashMismatch { expected: B256, got: B256 },
+    StateRootMismatch { expected: B256, got: B256 },
+}
+
+impl ReasonCode for ContinuityError {
+    fn code(&self) -> &'static str {
+        match self {
+            ContinuityError::ParentHashMismatch { .. } => "parent_hash_mismatch",
+            ContinuityError::StateRootMismatch { .. } => "state_root_mismatch",
+        }
+    }
+}
+
+impl fmt::Display for ContinuityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContinuityError::ParentHashMismatch { expected, got } => write!(
+                f,
+                "parent hash {got} does not match the previous block {expected}"
+            ),
+            ContinuityError::StateRootMismatch { expected, got } => write!(
+                f,
+                "witness starts from state root {got}, not the state root {expected} of the previous block"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ContinuityError {}
+
+impl ReasonCode for StatelessValidationError {
+    // Spelled out instead of derived from the variant names, so that a rename in reth does not
+    // change the codes clients match on
+    #[allow(unreachable_patterns)]
+    fn code(&self) -> &'static str {
+        use StatelessValidationError::*;
+        match self {
+            AncestorHeaderLimitExceeded { .. } => "ancestor_header_limit_exceeded",
+            InvalidAncestorChain => "invalid_ancestor_chain",
+            WitnessRevealFailed { .. } => "witness_reveal_failed",
+            StatelessExecutionFailed(_) => "stateless_execution_failed",
+            ConsensusValidationFailed(_) => "consensus_validation_failed",
+            StatelessStateRootCalculationFailed => "stateless_state_root_calculation_failed",
+            StatelessPreStateRootCalculationFailed => "stateless_pre_state_root_calculation_failed",
+            MissingAncestorHeader => "missing_ancestor_header",
+            HeaderDeserializationFailed => "header_deserialization_failed",
+            PostStateRootMismatch { .. } => "post_state_root_mismatch",
+            PreStateRootMismatch { .. } => "pre_state_root_mismatch",
+            // A variant added by a newer reth, until it gets its own code
+            _ => "stateless_validation_failed",
+        }
+    }
+}
+
+/// Check that `input` is the child of `previous`, and that its witness starts from the
+/// state `previous` ended with
+pub fn check_continuity(previous: &Header, input: &ClientInput) -> Result<(), ContinuityError> {
+    let expected = previous.hash_slow();
+    if input.block.header.parent_hash != expected {
+        return Err(ContinuityError::ParentHashMismatch {
+            expected,
+            got: input.block.header.parent_hash,
+        });
+    }
+    // A witness whose headers do not decode is left to `stateless_validation` to reject
+    let parent = input
+        .witness
+        .headers
+        .last()
+        .and_then(|encoded| Header::decode(&mut encoded.as_ref()).ok());
+    if let Some(parent) = parent.filter(|parent| parent.state_root != previous.state_root) {
+        return Err(ContinuityError::StateRootMismatch {
+            expected: previous.state_root,
+            got: parent.state_root,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        let mismatch = ContinuityError::ParentHashMismatch {
+            expected: B256::ZERO,
+            got: B256::repeat_byte(1),
+        };
+        assert_eq!(mismatch.code(), "parent_hash_mismatch");
+        assert_eq!(BlockError::from(mismatch).code(), "parent_hash_mismatch");
+
+        let error = StatelessValidationError::PostStateRootMismatch {
+            got: B256::ZERO,
+            expected: B256::repeat_byte(1),
+        };
+        assert_eq!(BlockError::from(error).code(), "post_state_root_mismatch");
+    }
+}
@@ -0,0 +1,543 @@
+//! Profile mode: the stateless validation of a block, split into cycle regions.
+//!
+//! `stateless_validation` validates a block in one call, so the regions below it cannot be
+//! emitted from the outside. `validate_block` runs its steps in the same order, at the reth rev
+//! pinned in `Cargo.toml`, and wraps the witness, each transaction, each precompile call and the
+//! state root in a region. The tests run both on the same blocks and expect the same outcome, so
+//! they catch a reth bump that changes `stateless_validation`.
+
+use alloy_consensus::Header;
+use alloy_evm::eth::{EthEvm, EthEvmContext};
+use alloy_evm::{EvmEnv, EvmFactory};
+use alloy_primitives::{keccak256, map::B256Map, Address, Bytes, B256, U256};
+use alloy_rlp::{Decodable, Encodable};
+use alloy_trie::{TrieAccount, EMPTY_ROOT_HASH};
+use reth_chainspec::ChainSpec;
+use reth_consensus::{Consensus, HeaderValidator};
+use reth_ethereum_consensus::{validate_block_post_execution, EthBeaconConsensus};
+use reth_evm::execute::BlockExecutor;
+use reth_evm::ConfigureEvm;
+use reth_evm_ethereum::EthEvmConfig;
+use reth_primitives_traits::{Block as _, SealedHeader};
+use reth_revm::db::{states::bundle_state::BundleRetention, State};
+use reth_stateless::validation::StatelessValidationError;
+use reth_stateless::{ClientInput, ExecutionWitness};
+use reth_trie_common::{HashedPostState, KeccakKeyHasher, Nibbles};
+use reth_trie_sparse::{blinded::DefaultBlindedProviderFactory, SparseStateTrie};
+use revm::context::{BlockEnv, CfgEnv, Context, ContextTr, TxEnv};
+use revm::context_interface::result::{EVMError, HaltReason};
+use revm::handler::{EthPrecompiles, PrecompileProvider};
+use revm::inspector::NoOpInspector;
+use revm::interpreter::{InputsImpl, InterpreterResult};
+use revm::precompile::{PrecompileSpecId, Precompiles};
+use revm::primitives::hardfork::SpecId;
+use revm::state::{AccountInfo, Bytecode};
+use revm::{Database, Inspector, MainBuilder, MainContext};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::regions::{self, PrecompileClass};
+
+/// The paths of the regions that are open, innermost last
+static OPEN_REGIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A cycle region nested in the regions that are open, which ends when dropped
+#[must_use]
+pub struct Region {
+    path: String,
+}
+
+impl Region {
+    pub fn start(name: &str) -> Self {
+        let mut open = OPEN_REGIONS.lock().unwrap();
+        let path = match open.last() {
+            Some(parent) => format!("{parent}/{name}"),
+            None => name.to_string(),
+        };
+        println!("cycle-tracker-report-start: {path}");
+        open.push(path.clone());
+        Region { path }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        println!("cycle-tracker-report-end: {}", self.path);
+        OPEN_REGIONS.lock().unwrap().pop();
+    }
+}
+
+/// Validate a block like `stateless_validation`, emitting the regions of profile mode. Fails
+/// with the same error, or panics, for the same blocks.
+pub fn validate_block(
+    input: ClientInput,
+    chain_spec: Arc<ChainSpec>,
+) -> Result<B256, StatelessValidationError> {
+    let ClientInput { block, witness } = input;
+    let number = block.header.number;
+    // Like `stateless_validation`, which has no error for it at the pinned rev
+    let block = block
+        .try_into_recovered()
+        .expect("failed to recover the signers of the block");
+
+    let witness_region = Region::start(regions::WITNESS);
+    let mut ancestor_headers: Vec<Header> = witness
+        .headers
+        .iter()
+        .map(|encoded| Header::decode(&mut encoded.as_ref()))
+        .collect::<Result<_, _>>()
+        .map_err(|_| StatelessValidationError::HeaderDeserializationFailed)?;
+    ancestor_headers.sort_by_key(|header| header.number);
+    if ancestor_headers.len() > BLOCKHASH_SERVE_WINDOW {
+        return Err(StatelessValidationError::AncestorHeaderLimitExceeded {
+            count: ancestor_headers.len(),
+            limit: BLOCKHASH_SERVE_WINDOW,
+        });
+    }
+    let ancestor_hashes = ancestor_hashes(block.header(), &ancestor_headers)?;
+    let parent = ancestor_headers
+        .last()
+        .cloned()
+        .ok_or(StatelessValidationError::MissingAncestorHeader)?;
+    drop(witness_region);
+
+    // The consensus checks come before the witness is revealed
+    let consensus = EthBeaconConsensus::new(chain_spec.clone());
+    let sealed = block.sealed_block();
+    consensus.validate_header(sealed.sealed_header())?;
+    let pre_state_root = parent.state_root;
+    consensus
+        .validate_header_against_parent(sealed.sealed_header(), &SealedHeader::seal_slow(parent))?;
+    consensus.validate_block_pre_execution(sealed)?;
+
+    let (mut trie, bytecode) = {
+        let _region = Region::start(regions::WITNESS);
+        reveal_witness(&witness, pre_state_root)?
+    };
+
+    let (output, bundle) = {
+        let db = WitnessDatabase {
+            trie: &trie,
+            bytecode,
+            ancestor_hashes,
+        };
+        let mut state = State::builder()
+            .with_database(db)
+            .with_bundle_update()
+            .without_state_clear()
+            .build();
+        let evm_config = EthEvmConfig::new_with_evm_factory(chain_spec.clone(), ProfiledEvmFactory);
+        let execution_failed = |e: &dyn core::fmt::Display| {
+            StatelessValidationError::StatelessExecutionFailed(e.to_string())
+        };
+
+        let mut executor = evm_config.executor_for_block(&mut state, &block);
+        executor
+            .apply_pre_execution_changes()
+            .map_err(|e| execution_failed(&e))?;
+        for (index, transaction) in block.transactions_recovered().enumerate() {
+            let _region = Region::start(&regions::transaction(number, index));
+            executor
+                .execute_transaction(transaction)
+                .map_err(|e| execution_failed(&e))?;
+        }
+        let output = executor
+            .apply_post_execution_changes()
+            .map_err(|e| execution_failed(&e))?;
+        state.merge_transitions(BundleRetention::Reverts);
+        (output, state.take_bundle())
+    };
+    validate_block_post_execution(&block, &chain_spec, &output.receipts, &output.requests)
+        .map_err(StatelessValidationError::ConsensusValidationFailed)?;
+
+    let state_root = {
+        let _region = Region::start(regions::STATE_ROOT);
+        let hashed_state = HashedPostState::from_bundle_state::<KeccakKeyHasher>(&bundle.state);
+        state_root(&mut trie, hashed_state)?
+    };
+    if state_root != block.header().state_root {
+        return Err(StatelessValidationError::PostStateRootMismatch {
+            got: state_root,
+            expected: block.header().state_root,
+        });
+    }
+    Ok(block.hash())
+}
+
+/// How many ancestors `BLOCKHASH` can reach
+const BLOCKHASH_SERVE_WINDOW: usize = 256;
+
+/// The hashes of the ancestors of `header`, checking that they form a chain ending at its parent
+fn ancestor_hashes(
+    header: &Header,
+    ancestors: &[Header],
+) -> Result<BTreeMap<u64, B256>, StatelessValidationError> {
+    let mut hashes = BTreeMap::new();
+    let mut child = header;
+    for parent in ancestors.iter().rev() {
+        let parent_hash = child.parent_hash;
+        if parent_hash != parent.hash_slow() || parent.number + 1 != child.number {
+            return Err(StatelessValidationError::InvalidAncestorChain);
+        }
+        hashes.insert(parent.number, parent_hash);
+        child = parent;
+    }
+    Ok(hashes)
+}
+
+/// Build the state tries from the witness, checking that they hash to `pre_state_root`
+fn reveal_witness(
+    witness: &ExecutionWitness,
+    pre_state_root: B256,
+) -> Result<(SparseStateTrie, B256Map<Bytecode>), StatelessValidationError> {
+    let nodes: B256Map<Bytes> = witness
+        .state
+        .iter()
+        .map(|node| (keccak256(node), node.clone()))
+        .collect();
+    let bytecode = witness
+        .codes
+        .iter()
+        .map(|code| (keccak256(code), Bytecode::new_raw(code.clone())))
+        .collect();
+
+    let mut trie = SparseStateTrie::new(DefaultBlindedProviderFactory);
+    trie.reveal_witness(pre_state_root, &nodes)
+        .map_err(|_| StatelessValidationError::WitnessRevealFailed { pre_state_root })?;
+    let root = trie
+        .root()
+        .map_err(|_| StatelessValidationError::StatelessPreStateRootCalculationFailed)?;
+    if root != pre_state_root {
+        return Err(StatelessValidationError::PreStateRootMismatch {
+            got: root,
+            expected: pre_state_root,
+        });
+    }
+    Ok((trie, bytecode))
+}
+
+/// Apply the changes of the block to the tries and compute the post-state root
+fn state_root(
+    trie: &mut SparseStateTrie,
+    state: HashedPostState,
+) -> Result<B256, StatelessValidationError> {
+    let mut storages: Vec<_> = state.storages.into_iter().collect();
+    storages.sort_unstable_by_key(|(address, _)| *address);
+    for (address, storage) in storages {
+        let mut storage_trie = trie.take_storage_trie(&address).unwrap_or_default();
+        if storage.wiped {
+            storage_trie.wipe().map_err(failed)?;
+        }
+        let mut slots: Vec<_> = storage.storage.into_iter().collect();
+        slots.sort_unstable_by_key(|(slot, _)| *slot);
+        for (slot, value) in slots {
+            let path = Nibbles::unpack(slot);
+            if value.is_zero() {
+                storage_trie.remove_leaf(&path).map_err(failed)?;
+            } else {
+                let encoded = alloy_rlp::encode_fixed_size(&value).to_vec();
+                storage_trie.update_leaf(path, encoded).map_err(failed)?;
+            }
+        }
+        storage_trie.root();
+        trie.insert_storage_trie(address, storage_trie);
+    }
+
+    let mut accounts: Vec<_> = state.accounts.into_iter().collect();
+    accounts.sort_unstable_by_key(|(address, _)| *address);
+    let mut encoded = Vec::new();
+    for (address, account) in accounts {
+        let path = Nibbles::unpack(address);
+        let account = account.unwrap_or_default();
+        let storage_root = match trie.storage_trie_mut(&address) {
+            Some(storage_trie) => storage_trie.root().unwrap_or(EMPTY_ROOT_HASH),
+            None => match trie.get_account_value(&address) {
+                Some(value) => {
+                    TrieAccount::decode(&mut value.as_slice())
+                        .map_err(failed)?
+                        .storage_root
+                }
+                None => EMPTY_ROOT_HASH,
+            },
+        };
+        if account.is_empty() && storage_root == EMPTY_ROOT_HASH {
+            trie.remove_account_leaf(&path).map_err(failed)?;
+        } else {
+            encoded.clear();
+            account.into_trie_account(storage_root).encode(&mut encoded);
+            trie.update_account_leaf(path, encoded.clone())
+                .map_err(failed)?;
+        }
+    }
+    trie.root().map_err(failed)
+}
+
+fn failed<E>(_: E) -> StatelessValidationError {
+    StatelessValidationError::StatelessStateRootCalculationFailed
+}
+
+/// The state the block executes on, read from the revealed tries
+struct WitnessDatabase<'a> {
+    trie: &'a SparseStateTrie,
+    bytecode: B256Map<Bytecode>,
+    ancestor_hashes: BTreeMap<u64, B256>,
+}
+
+impl Database for WitnessDatabase<'_> {
+    type Error = reth_errors::ProviderError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let Some(value) = self.trie.get_account_value(&keccak256(address)) else {
+            return Ok(None);
+        };
+        let account = TrieAccount::decode(&mut value.as_slice())?;
+        Ok(Some(AccountInfo {
+            balance: account.balance,
+            nonce: account.nonce,
+            code_hash: account.code_hash,
+            code: None,
+        }))
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.bytecode.get(&code_hash).cloned().ok_or_else(|| {
+            reth_errors::ProviderError::TrieWitnessError(format!("missing code {code_hash}"))
+        })
+    }
+
+    fn storage(&mut self, address: Address, slot: U256) -> Result<U256, Self::Error> {
+        let value = self
+            .trie
+            .get_storage_slot_value(&keccak256(address), &keccak256(B256::from(slot)));
+        match value {
+            Some(value) => Ok(U256::decode(&mut value.as_slice())?),
+            None => Ok(U256::ZERO),
+        }
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        self.ancestor_hashes
+            .get(&number)
+            .copied()
+            .ok_or(reth_errors::ProviderError::StateForNumberNotFound(number))
+    }
+}
+
+/// Builds Ethereum EVMs whose precompile calls are regions
+#[derive(Debug, Clone, Copy, Default)]
+struct ProfiledEvmFactory;
+
+impl EvmFactory for ProfiledEvmFactory {
+    type Evm<DB: Database, I: Inspector<EthEvmContext<DB>>> = EthEvm<DB, I, ProfiledPrecompiles>;
+    type Context<DB: Database> = EthEvmContext<DB>;
+    type Tx = TxEnv;
+    type Error<DBError: core::error::Error + Send + Sync + 'static> = EVMError<DBError>;
+    type HaltReason = HaltReason;
+    type Spec = SpecId;
+
+    fn create_evm<DB: Database>(&self, db: DB, input: EvmEnv) -> Self::Evm<DB, NoOpInspector> {
+        self.create_evm_with_inspector(db, input, NoOpInspector {})
+    }
+
+    fn create_evm_with_inspector<DB: Database, I: Inspector<Self::Context<DB>>>(
+        &self,
+        db: DB,
+        input: EvmEnv,
+        inspector: I,
+    ) -> Self::Evm<DB, I> {
+        let precompiles = ProfiledPrecompiles::new(input.cfg_env.spec);
+        let evm = Context::mainnet()
+            .with_db(db)
+            .with_cfg(input.cfg_env)
+            .with_block(input.block_env)
+            .build_mainnet_with_inspector(inspector)
+            .with_precompiles(precompiles);
+        EthEvm::new(evm, false)
+    }
+}
+
+/// The Ethereum precompiles, each call in the region of its class
+#[derive(Debug, Clone)]
+struct ProfiledPrecompiles {
+    inner: EthPrecompiles,
+}
+
+impl ProfiledPrecompiles {
+    fn new(spec: SpecId) -> Self {
+        Self {
+            inner: EthPrecompiles {
+                precompiles: Precompiles::new(PrecompileSpecId::from_spec_id(spec)),
+                spec,
+            },
+        }
+    }
+}
+
+impl<CTX> PrecompileProvider<CTX> for ProfiledPrecompiles
+where
+    CTX: ContextTr<Cfg = CfgEnv, Block = BlockEnv>,
+    EthPrecompiles: PrecompileProvider<CTX, Output = InterpreterResult>,
+{
+    type Output = InterpreterResult;
+
+    fn set_spec(&mut self, spec: SpecId) -> bool {
+        <EthPrecompiles as PrecompileProvider<CTX>>::set_spec(&mut self.inner, spec)
+    }
+
+    fn run(
+        &mut self,
+        context: &mut CTX,
+        address: &Address,
+        inputs: &InputsImpl,
+        is_static: bool,
+        gas_limit: u64,
+    ) -> Result<Option<Self::Output>, String> {
+        let _region = PrecompileClass::from_address(address)
+            .filter(|_| self.inner.contains(address))
+            .map(|class| Region::start(&regions::precompile(class)));
+        self.inner
+            .run(context, address, inputs, is_static, gas_limit)
+    }
+
+    fn warm_addresses(&self) -> Box<impl Iterator<Item = Address>> {
+        <EthPrecompiles as PrecompileProvider<CTX>>::warm_addresses(&self.inner)
+    }
+
+    fn contains(&self, address: &Address) -> bool {
+        <EthPrecompiles as PrecompileProvider<CTX>>::contains(&self.inner, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloy_eips::eip2718::Decodable2718;
+    use alloy_genesis::Genesis;
+    use reth_ethereum_primitives::TransactionSigned;
+    use reth_stateless::validation::stateless_validation;
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crate::ReasonCode;
+
+    /// The hash of a valid block or the code of an invalid one, `None` for a panic
+    type Outcome = Option<Result<B256, &'static str>>;
+
+    fn outcome(validate: impl FnOnce() -> Result<B256, StatelessValidationError>) -> Outcome {
+        panic::catch_unwind(AssertUnwindSafe(validate))
+            .ok()
+            .map(|result| result.map_err(|e| e.code()))
+    }
+
+    /// Validate `input` both ways, expecting the same outcome
+    fn same_outcome(input: &ClientInput, spec: &Arc<ChainSpec>) -> Outcome {
+        let expected = outcome(|| {
+            stateless_validation(input.block.clone(), input.witness.clone(), spec.clone())
+        });
+        let profiled = outcome(|| validate_block(input.clone(), spec.clone()));
+        assert_eq!(profiled, expected);
+        expected
+    }
+
+    /// An empty block on top of the genesis of a chain without forks, whose post-state holds
+    /// the block reward of its beneficiary
+    fn first_block() -> (ClientInput, Arc<ChainSpec>) {
+        let spec = Arc::new(ChainSpec::from(Genesis {
+            gas_limit: 30_000_000,
+            ..Genesis::default()
+        }));
+        let parent = spec.genesis_header().clone();
+        let header = Header {
+            number: 1,
+            parent_hash: parent.hash_slow(),
+            timestamp: parent.timestamp + 12,
+            gas_limit: parent.gas_limit,
+            ..Header::default()
+        };
+        let mut input = ClientInput {
+            block: alloy_consensus::Block {
+                header,
+                body: Default::default(),
+            },
+            witness: ExecutionWitness {
+                // The root of the empty state of the genesis
+                state: vec![Bytes::from_static(&[alloy_rlp::EMPTY_STRING_CODE])],
+                headers: vec![alloy_rlp::encode(&parent).into()],
+                ..Default::default()
+            },
+        };
+        // The post-state root is taken from reth, which computes the reward
+        match stateless_validation(input.block.clone(), input.witness.clone(), spec.clone()) {
+            Err(StatelessValidationError::PostStateRootMismatch { got, .. }) => {
+                input.block.header.state_root = got
+            }
+            other => panic!("unexpected outcome of the fixture: {other:?}"),
+        }
+        (input, spec)
+    }
+
+    #[test]
+    fn test_same_outcome_as_reth() {
+        let (valid, spec) = first_block();
+        assert_eq!(
+            same_outcome(&valid, &spec),
+            Some(Ok(valid.block.header.hash_slow()))
+        );
+
+        let mut input = valid.clone();
+        input.block.header.state_root = B256::ZERO;
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("post_state_root_mismatch"))
+        );
+
+        let mut input = valid.clone();
+        input.block.header.gas_used = input.block.header.gas_limit + 1;
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("consensus_validation_failed"))
+        );
+        // A block failing the consensus checks and the witness fails the consensus checks
+        input.witness.state.clear();
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("consensus_validation_failed"))
+        );
+
+        let mut input = valid.clone();
+        input.witness.state.clear();
+        same_outcome(&input, &spec);
+
+        let mut input = valid.clone();
+        input.witness.headers.clear();
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("missing_ancestor_header"))
+        );
+
+        let mut input = valid.clone();
+        input.witness.headers = vec![Bytes::from_static(&[0xc0])];
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("header_deserialization_failed"))
+        );
+
+        let mut input = valid.clone();
+        let other_parent = Header {
+            gas_limit: 1,
+            ..Header::default()
+        };
+        input.witness.headers = vec![alloy_rlp::encode(&other_parent).into()];
+        assert_eq!(
+            same_outcome(&input, &spec),
+            Some(Err("invalid_ancestor_chain"))
+        );
+
+        // A legacy transaction signed with r = s = 0, whose signer cannot be recovered
+        let mut input = valid;
+        let encoded = [0xc9, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x1b, 0x80, 0x80];
+        let transaction = TransactionSigned::decode_2718(&mut encoded.as_slice()).unwrap();
+        input.block.body.transactions.push(transaction);
+        same_outcome(&input, &spec);
+    }
+}
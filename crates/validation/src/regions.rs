@@ -0,0 +1,164 @@
+//! Names of the cycle regions a guest emits in profile mode.
+//!
+//! A region is named after what it measures, below the path of the regions it runs in, like
+//! `validation/block:10/tx:10:3/precompile:ecrecover`. The guest emits them with these
+//! functions and the host attributes them with [`Region::parse`], so the two cannot drift.
+
+use alloy_primitives::Address;
+
+/// Reading the input
+pub const READ_INPUT: &str = "read_input";
+/// Validating all the blocks of the input
+pub const VALIDATION: &str = "validation";
+/// Checking the ancestor headers and building the tries from the witness
+pub const WITNESS: &str = "witness";
+/// Computing the post-state root
+pub const STATE_ROOT: &str = "state_root";
+
+/// Validating the block `number`
+pub fn block(number: u64) -> String {
+    format!("block:{number}")
+}
+
+/// Executing the transaction at `index` in the block `block_number`
+pub fn transaction(block_number: u64, index: usize) -> String {
+    format!("tx:{block_number}:{index}")
+}
+
+/// The calls to the precompiles of `class`
+pub fn precompile(class: PrecompileClass) -> String {
+    format!("precompile:{}", class.name())
+}
+
+/// The precompiles of the EVM, with the BLS12-381 operations counted as one class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrecompileClass {
+    Ecrecover,
+    Sha256,
+    Ripemd160,
+    Identity,
+    Modexp,
+    Bn254Add,
+    Bn254Mul,
+    Bn254Pairing,
+    Blake2f,
+    PointEvaluation,
+    Bls12381,
+}
+
+impl PrecompileClass {
+    /// The class of the precompile at `address`, `None` for other addresses
+    pub fn from_address(address: &Address) -> Option<Self> {
+        let (prefix, last) = address.as_slice().split_at(19);
+        if prefix.iter().any(|&byte| byte != 0) {
+            return None;
+        }
+        Some(match last[0] {
+            0x01 => PrecompileClass::Ecrecover,
+            0x02 => PrecompileClass::Sha256,
+            0x03 => PrecompileClass::Ripemd160,
+            0x04 => PrecompileClass::Identity,
+            0x05 => PrecompileClass::Modexp,
+            0x06 => PrecompileClass::Bn254Add,
+            0x07 => PrecompileClass::Bn254Mul,
+            0x08 => PrecompileClass::Bn254Pairing,
+            0x09 => PrecompileClass::Blake2f,
+            0x0a => PrecompileClass::PointEvaluation,
+            0x0b..=0x11 => PrecompileClass::Bls12381,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PrecompileClass::Ecrecover => "ecrecover",
+            PrecompileClass::Sha256 => "sha256",
+            PrecompileClass::Ripemd160 => "ripemd160",
+            PrecompileClass::Identity => "identity",
+            PrecompileClass::Modexp => "modexp",
+            PrecompileClass::Bn254Add => "bn254_add",
+            PrecompileClass::Bn254Mul => "bn254_mul",
+            PrecompileClass::Bn254Pairing => "bn254_pairing",
+            PrecompileClass::Blake2f => "blake2f",
+            PrecompileClass::PointEvaluation => "point_evaluation",
+            PrecompileClass::Bls12381 => "bls12_381",
+        }
+    }
+}
+
+/// A region named by the functions of this module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Region<'a> {
+    Block(u64),
+    Transaction {
+        block_number: u64,
+        index: usize,
+    },
+    /// The name of the precompile class
+    Precompile(&'a str),
+    Witness,
+    StateRoot,
+}
+
+impl<'a> Region<'a> {
+    /// Parse the last part of a region path, `None` for regions that are not attributed
+    pub fn parse(path: &'a str) -> Option<Self> {
+        let leaf = path.rsplit('/').next().unwrap_or(path);
+        let (kind, detail) = leaf.split_once(':').unwrap_or((leaf, ""));
+        match kind {
+            "block" => detail.parse().ok().map(Region::Block),
+            "tx" => {
+                let (number, index) = detail.split_once(':')?;
+                Some(Region::Transaction {
+                    block_number: number.parse().ok()?,
+                    index: index.parse().ok()?,
+                })
+            }
+            "precompile" if !detail.is_empty() => Some(Region::Precompile(detail)),
+            WITNESS => Some(Region::Witness),
+            STATE_ROOT => Some(Region::StateRoot),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_named_regions() {
+        let path = format!("{VALIDATION}/{}/{}", block(10), transaction(10, 3));
+        assert_eq!(
+            Region::parse(&path),
+            Some(Region::Transaction {
+                block_number: 10,
+                index: 3
+            })
+        );
+        assert_eq!(Region::parse(&block(10)), Some(Region::Block(10)));
+
+        let ecrecover = PrecompileClass::from_address(&Address::with_last_byte(1)).unwrap();
+        let path = format!("{path}/{}", precompile(ecrecover));
+        assert_eq!(Region::parse(&path), Some(Region::Precompile("ecrecover")));
+
+        assert_eq!(Region::parse(WITNESS), Some(Region::Witness));
+        assert_eq!(Region::parse(STATE_ROOT), Some(Region::StateRoot));
+        assert_eq!(Region::parse(VALIDATION), None);
+        assert_eq!(Region::parse("tx:10"), None);
+    }
+
+    #[test]
+    fn test_precompile_classes() {
+        let class = |byte| PrecompileClass::from_address(&Address::with_last_byte(byte));
+        assert_eq!(class(0x08), Some(PrecompileClass::Bn254Pairing));
+        assert_eq!(class(0x0f), Some(PrecompileClass::Bls12381));
+        assert_eq!(class(0x00), None);
+        assert_eq!(class(0x12), None);
+
+        let mut address = [0; 20];
+        address[0] = 1;
+        address[19] = 1;
+        assert_eq!(PrecompileClass::from_address(&Address::from(address)), None);
+    }
+}
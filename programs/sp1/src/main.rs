@@ -4,18 +4,144 @@
 
 extern crate alloc;
 
-use reth_stateless::{ClientInput, validation::stateless_validation};
+use alloc::sync::Arc;
+use alloy_consensus::Header;
+use alloy_genesis::Genesis;
+use alloy_primitives::B256;
+use poost_validation::profile::{self, Region};
+use poost_validation::{check_continuity, regions, BlockError, ReasonCode};
+use reth_chainspec::{ChainSpec, HOLESKY, HOODI, MAINNET, SEPOLIA};
+use reth_stateless::{validation::stateless_validation, ClientInput};
+use serde::{Deserialize, Serialize};
 
 sp1_zkvm::entrypoint!(main);
+
+/// Read after the `ClientInput`, mirrors `GuestChain` in `src/program/chain.rs`
+#[derive(Deserialize)]
+enum ChainInput {
+    Id(u64),
+    /// The genesis as JSON
+    Genesis(String),
+}
+
+impl ChainInput {
+    fn spec(self) -> Arc<ChainSpec> {
+        match self {
+            ChainInput::Id(1) => MAINNET.clone(),
+            ChainInput::Id(11_155_111) => SEPOLIA.clone(),
+            ChainInput::Id(17_000) => HOLESKY.clone(),
+            ChainInput::Id(560_048) => HOODI.clone(),
+            ChainInput::Id(id) => panic!("unknown chain id {id}"),
+            ChainInput::Genesis(genesis) => {
+                let genesis: Genesis = serde_json::from_str(&genesis).expect("invalid genesis");
+                Arc::new(ChainSpec::from(genesis))
+            }
+        }
+    }
+}
+
+/// Committed as the public values, mirrors `ValidationOutcome` in `src/program/outcome.rs`
+#[derive(Serialize)]
+struct ValidationOutcome {
+    /// Chain id of the chain spec the blocks were validated against
+    chain_id: u64,
+    valid: bool,
+    /// Number of the first block
+    first_block_number: u64,
+    /// Parent hash of the first block
+    parent_hash: B256,
+    /// Hash of the last block, when every block is valid
+    block_hash: Option<B256>,
+    /// Why a block is invalid
+    reason: Option<InvalidReason>,
+}
+
+#[derive(Serialize)]
+struct InvalidReason {
+    /// Number of the first invalid block
+    block_number: u64,
+    /// Name of the validation error, like `post_state_root_mismatch`
+    code: String,
+    message: String,
+}
+
+fn invalid_reason(block_number: u64, error: BlockError) -> InvalidReason {
+    InvalidReason {
+        block_number,
+        code: error.code().to_string(),
+        message: error.to_string(),
+    }
+}
+
 /// Entry point.
+///
+/// Reads the first block, the chain, the number of blocks that follow and those blocks, which
+/// must each be the child of the previous one, and whether to profile the run.
 pub fn main() {
-    println!("cycle-tracker-report-start: read_input");
-    let input = sp1_zkvm::io::read::<ClientInput>();
-    println!("cycle-tracker-report-end: read_input");
+    let read_input = Region::start(regions::READ_INPUT);
+    let first = sp1_zkvm::io::read::<ClientInput>();
+    let chain_spec = sp1_zkvm::io::read::<ChainInput>().spec();
+    let following = sp1_zkvm::io::read::<u32>();
+    let mut blocks = Vec::with_capacity(following as usize + 1);
+    blocks.push(first);
+    for _ in 0..following {
+        blocks.push(sp1_zkvm::io::read::<ClientInput>());
+    }
+    let profile = sp1_zkvm::io::read::<bool>();
+    drop(read_input);
+
+    let chain_id = chain_spec.chain.id();
+    let first_block_number = blocks[0].block.header.number;
+    let parent_hash = blocks[0].block.header.parent_hash;
 
-    let chain_spec = &*reth_chainspec::MAINNET;
+    let validation = Region::start(regions::VALIDATION);
+    let mut previous: Option<Header> = None;
+    let mut result = Ok(B256::ZERO);
+    for input in blocks {
+        let number = input.block.header.number;
+        if let Some(previous) = &previous {
+            if let Err(e) = check_continuity(previous, &input) {
+                result = Err(invalid_reason(number, e.into()));
+                break;
+            }
+        }
+        let header = input.block.header.clone();
+        // Profile mode names regions after what they measure, see `src/program/attribution.rs`
+        let validated = if profile {
+            let _block = Region::start(&regions::block(number));
+            profile::validate_block(input, chain_spec.clone())
+        } else {
+            stateless_validation(input.block, input.witness, chain_spec.clone())
+        };
+        match validated {
+            Ok(block_hash) => result = Ok(block_hash),
+            Err(e) => {
+                result = Err(invalid_reason(number, e.into()));
+                break;
+            }
+        }
+        previous = Some(header);
+    }
+    drop(validation);
 
-    println!("cycle-tracker-report-start: validation");
-    stateless_validation(input.block, input.witness, chain_spec.clone()).unwrap();
-    println!("cycle-tracker-report-end: validation");
+    // An invalid block is an outcome, not a failure, so that its invalidity can be proven too
+    let outcome = match result {
+        Ok(block_hash) => ValidationOutcome {
+            chain_id,
+            valid: true,
+            first_block_number,
+            parent_hash,
+            block_hash: Some(block_hash),
+            reason: None,
+        },
+        Err(reason) => ValidationOutcome {
+            chain_id,
+            valid: false,
+            first_block_number,
+            parent_hash,
+            block_hash: None,
+            reason: Some(reason),
+        },
+    };
+    sp1_zkvm::io::commit(&outcome);
 }
@@ -0,0 +1,155 @@
+//! Statistics of repeated runs, and the archived form of a benchmark.
+//!
+//! A `BenchmarkReport` is meant to be stored and compared across builds and hosts: fields are
+//! only ever added to it, and a breaking change bumps `SCHEMA_VERSION`.
+
+mod calibration;
+mod history;
+mod memory;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use calibration::{Observation, Prediction, predict};
+pub use history::{BenchmarkFilter, BenchmarkHistory, RegressionReport};
+pub use memory::MemorySampler;
+
+use crate::common::ProgramID;
+use crate::endpoints::info::HardwareFingerprint;
+
+/// Version of the `BenchmarkReport` schema
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Summary of the samples of one measurement
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Statistics {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Nearest-rank 95th percentile
+    pub p95: f64,
+    /// Sample standard deviation, 0 for a single sample
+    pub stddev: f64,
+    /// The measured runs, in the order they ran
+    pub samples: Vec<f64>,
+}
+
+impl Statistics {
+    /// Summarize `samples`, which must not be empty
+    pub fn new(samples: Vec<f64>) -> Self {
+        let mut sorted = samples.clone();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        // The two middle samples are the same one when `n` is odd
+        let median = (sorted[(n - 1) / 2] + sorted[n / 2]) / 2.0;
+        let p95 = sorted[(n * 95).div_ceil(100) - 1];
+        let stddev = if n > 1 {
+            let variance = sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            variance.sqrt()
+        } else {
+            0.0
+        };
+
+        Statistics {
+            min: sorted[0],
+            max: sorted[n - 1],
+            mean,
+            median,
+            p95,
+            stddev,
+            samples,
+        }
+    }
+}
+
+/// What a benchmark measures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BenchmarkMode {
+    #[default]
+    Execute,
+    Prove,
+    /// Execute, then prove, in every run
+    Both,
+}
+
+impl BenchmarkMode {
+    pub fn executes(self) -> bool {
+        matches!(self, BenchmarkMode::Execute | BenchmarkMode::Both)
+    }
+
+    pub fn proves(self) -> bool {
+        matches!(self, BenchmarkMode::Prove | BenchmarkMode::Both)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ExecuteStatistics {
+    pub wall_time_milliseconds: Statistics,
+    pub cycles: Statistics,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProveStatistics {
+    pub wall_time_milliseconds: Statistics,
+    /// Proving time as reported by the backend
+    pub proving_time_milliseconds: Statistics,
+    pub proof_size: Statistics,
+    /// Peak resident memory of the server while proving, when the platform reports it
+    #[serde(default)]
+    pub peak_memory_bytes: Option<Statistics>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BenchmarkReport {
+    pub schema_version: u32,
+    /// Job the benchmark ran as
+    pub benchmark_id: Uuid,
+    pub program_id: ProgramID,
+    /// Program version that was benchmarked
+    pub version: String,
+    /// SHA-256 of the program input
+    pub input_hash: String,
+    pub mode: BenchmarkMode,
+    /// Runs made before measuring, and left out of the statistics
+    pub warmup: usize,
+    /// Measured runs
+    pub runs: usize,
+    /// Milliseconds since the unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub hardware: HardwareFingerprint,
+    /// Set when the mode executes
+    pub execute: Option<ExecuteStatistics>,
+    /// Set when the mode proves
+    pub prove: Option<ProveStatistics>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let stats = Statistics::new(vec![4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 4.0);
+        assert_eq!(stats.mean, 2.5);
+        assert_eq!(stats.median, 2.5);
+        assert_eq!(stats.p95, 4.0);
+        assert!((stats.stddev - 1.290_994).abs() < 1e-6);
+        // The samples keep their order
+        assert_eq!(stats.samples, [4.0, 1.0, 3.0, 2.0]);
+
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        let stats = Statistics::new(samples);
+        assert_eq!(stats.median, 50.5);
+        assert_eq!(stats.p95, 95.0);
+
+        let stats = Statistics::new(vec![7.0]);
+        assert_eq!((stats.median, stats.p95, stats.stddev), (7.0, 7.0, 0.0));
+    }
+}
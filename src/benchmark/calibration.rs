@@ -0,0 +1,153 @@
+//! Predicting the proving time and memory of an input from its cycles.
+//!
+//! The points come from benchmarks that both executed and proved on the host. With three or
+//! more points at different cycle counts, the prediction comes from a least-squares line and
+//! its 95% prediction interval. Otherwise it scales the cycles by the ratio seen so far, within
+//! the lowest and highest ratio.
+
+use serde::{Deserialize, Serialize};
+
+/// Two-sided 95% quantile of the normal distribution
+const Z_95: f64 = 1.96;
+
+/// Two-sided 95% quantiles of the Student-t distribution, by degrees of freedom from 1
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// Two-sided 95% quantile of the Student-t distribution with `df` degrees of freedom, at least 1.
+/// Past the table, the Cornish-Fisher expansion around the normal quantile is within 1e-3.
+fn t_95(df: usize) -> f64 {
+    if let Some(t) = T_95.get(df.max(1) - 1) {
+        return *t;
+    }
+    let df = df as f64;
+    let z = Z_95;
+    z + (z.powi(3) + z) / (4.0 * df)
+        + (5.0 * z.powi(5) + 16.0 * z.powi(3) + 3.0 * z) / (96.0 * df.powi(2))
+}
+
+/// One benchmark that both executed and proved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub cycles: f64,
+    pub proving_time_milliseconds: f64,
+    /// Absent for benchmarks recorded before the memory was measured
+    pub peak_memory_bytes: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Model {
+    /// A least-squares line over the cycles
+    Linear,
+    /// Proportional to the cycles, for too few points to fit a line
+    Proportional,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Prediction {
+    pub estimate: f64,
+    /// Lower end of the confidence range, never below 0
+    pub low: f64,
+    pub high: f64,
+    pub model: Model,
+    /// Number of points the prediction is based on
+    pub observations: usize,
+}
+
+/// Predict `y` at `x` from `points` of `(x, y)`, or `None` without any point with cycles
+pub fn predict(points: &[(f64, f64)], x: f64) -> Option<Prediction> {
+    let points: Vec<(f64, f64)> = points.iter().copied().filter(|(x, _)| *x > 0.0).collect();
+    if points.is_empty() {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+
+    if points.len() >= 3 && sxx > 0.0 {
+        let slope = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>()
+            / sxx;
+        let intercept = mean_y - slope * mean_x;
+        let residuals: f64 = points
+            .iter()
+            .map(|(x, y)| (y - intercept - slope * x).powi(2))
+            .sum();
+        let stderr = (residuals / (n - 2.0)).sqrt();
+
+        let estimate = intercept + slope * x;
+        // So few points leave the spread of the residuals itself uncertain, hence Student-t
+        let margin =
+            t_95(points.len() - 2) * stderr * (1.0 + 1.0 / n + (x - mean_x).powi(2) / sxx).sqrt();
+        return Some(Prediction {
+            estimate: estimate.max(0.0),
+            low: (estimate - margin).max(0.0),
+            high: (estimate + margin).max(0.0),
+            model: Model::Linear,
+            observations: points.len(),
+        });
+    }
+
+    let ratios = points.iter().map(|(x, y)| y / x);
+    let low = ratios.clone().fold(f64::INFINITY, f64::min);
+    let high = ratios.fold(f64::NEG_INFINITY, f64::max);
+    Some(Prediction {
+        estimate: mean_y / mean_x * x,
+        low: low * x,
+        high: high * x,
+        model: Model::Proportional,
+        observations: points.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_prediction() {
+        // 10 ms plus 2 ms per cycle, with some noise
+        let points = [(10.0, 31.0), (20.0, 49.0), (30.0, 71.0), (40.0, 89.0)];
+        let prediction = predict(&points, 50.0).unwrap();
+
+        assert_eq!(prediction.model, Model::Linear);
+        assert_eq!(prediction.observations, 4);
+        assert!((prediction.estimate - 110.0).abs() < 2.0);
+        assert!(prediction.low < prediction.estimate && prediction.estimate < prediction.high);
+
+        // Farther from the observations, the range widens
+        let far = predict(&points, 500.0).unwrap();
+        assert!(far.high - far.low > prediction.high - prediction.low);
+    }
+
+    #[test]
+    fn test_t_quantile() {
+        assert_eq!(t_95(1), 12.706);
+        assert_eq!(t_95(30), 2.042);
+        // Tabulated: 2.021 at 40, 2.000 at 60, 1.980 at 120
+        assert!((t_95(40) - 2.021).abs() < 1e-3);
+        assert!((t_95(60) - 2.000).abs() < 1e-3);
+        assert!((t_95(120) - 1.980).abs() < 1e-3);
+        assert!((t_95(100_000) - Z_95).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_proportional_prediction() {
+        let prediction = predict(&[(10.0, 20.0), (10.0, 30.0)], 100.0).unwrap();
+        assert_eq!(prediction.model, Model::Proportional);
+        assert_eq!(
+            (prediction.estimate, prediction.low, prediction.high),
+            (250.0, 200.0, 300.0)
+        );
+
+        assert_eq!(predict(&[], 100.0), None);
+        assert_eq!(predict(&[(0.0, 5.0)], 100.0), None);
+    }
+}
@@ -0,0 +1,462 @@
+//! Benchmark reports kept over time, and the regressions found in them.
+//!
+//! Reports are kept in memory and, when opened on a data directory, appended to
+//! `benchmarks.log` as JSON lines. A series is every benchmark of one input under one program
+//! id on one host. Its latest benchmark is compared against a rolling baseline made of the
+//! benchmarks before it, so a program bump shows up as a change within its series.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::{BenchmarkReport, Observation};
+use crate::common::ProgramID;
+use crate::config::RegressionPolicy;
+
+const LOG_FILE: &str = "benchmarks.log";
+
+/// Filters for listing benchmarks and checking them for regressions. Every field is optional.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BenchmarkFilter {
+    pub program_id: Option<ProgramID>,
+    /// Digest of the program ELF
+    pub version: Option<String>,
+    pub input_hash: Option<String>,
+    /// Digest of the hardware fingerprint
+    pub hardware: Option<String>,
+}
+
+impl BenchmarkFilter {
+    fn matches(&self, report: &BenchmarkReport) -> bool {
+        self.program_id
+            .as_ref()
+            .is_none_or(|program_id| &report.program_id == program_id)
+            && self
+                .version
+                .as_ref()
+                .is_none_or(|version| &report.version == version)
+            && self
+                .input_hash
+                .as_ref()
+                .is_none_or(|input_hash| &report.input_hash == input_hash)
+            && self
+                .hardware
+                .as_ref()
+                .is_none_or(|hardware| &report.hardware.digest == hardware)
+    }
+}
+
+/// A measurement compared between benchmarks, by its median
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Cycles,
+    ProvingTimeMilliseconds,
+}
+
+impl Metric {
+    const ALL: [Metric; 2] = [Metric::Cycles, Metric::ProvingTimeMilliseconds];
+
+    fn of(self, report: &BenchmarkReport) -> Option<f64> {
+        match self {
+            Metric::Cycles => report.execute.as_ref().map(|stats| stats.cycles.median),
+            Metric::ProvingTimeMilliseconds => report
+                .prove
+                .as_ref()
+                .map(|stats| stats.proving_time_milliseconds.median),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MetricCheck {
+    pub metric: Metric,
+    /// Mean of the metric over the baseline benchmarks
+    pub baseline: f64,
+    pub latest: f64,
+    pub change_percent: f64,
+    pub regressed: bool,
+    /// Benchmarks the baseline was made of, oldest first
+    pub baseline_benchmarks: Vec<Uuid>,
+    /// Program versions of the baseline benchmarks
+    pub baseline_versions: Vec<String>,
+}
+
+/// The latest benchmark of a series, checked against the benchmarks before it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SeriesCheck {
+    pub program_id: ProgramID,
+    pub input_hash: String,
+    /// Digest of the hardware fingerprint
+    pub hardware: String,
+    pub benchmark_id: Uuid,
+    pub version: String,
+    /// Only the metrics with a baseline
+    pub metrics: Vec<MetricCheck>,
+    pub regressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RegressionReport {
+    pub threshold_percent: f64,
+    pub baseline_runs: usize,
+    /// Whether any series regressed
+    pub regressed: bool,
+    /// Series with at least two benchmarks, regressed first
+    pub series: Vec<SeriesCheck>,
+}
+
+pub struct BenchmarkHistory {
+    reports: Mutex<Vec<BenchmarkReport>>,
+    policy: RegressionPolicy,
+    // Without a log, benchmarks only live in memory
+    log: Option<Mutex<File>>,
+}
+
+impl BenchmarkHistory {
+    pub fn new(policy: RegressionPolicy) -> Self {
+        Self {
+            reports: Mutex::new(Vec::new()),
+            policy,
+            log: None,
+        }
+    }
+
+    /// Open a history persisted in `dir`, restoring the benchmarks recorded there
+    pub fn open(dir: &Path, policy: RegressionPolicy) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOG_FILE);
+        let mut reports = Vec::new();
+        if path.exists() {
+            for (line_number, line) in BufReader::new(File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(report) => reports.push(report),
+                    // A crash in the middle of an append leaves a torn last line
+                    Err(e) => tracing::warn!(
+                        "Skipping unreadable line {} of {}: {}",
+                        line_number + 1,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        // Rewrite the log without unreadable lines, so the next append starts on a line of its own
+        let rewritten = dir.join(format!("{LOG_FILE}.tmp"));
+        {
+            let mut file = File::create(&rewritten)?;
+            for report in &reports {
+                writeln!(file, "{}", serde_json::to_string(report)?)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&rewritten, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self {
+            reports: Mutex::new(reports),
+            policy,
+            log: Some(Mutex::new(file)),
+        })
+    }
+
+    pub fn policy(&self) -> RegressionPolicy {
+        self.policy
+    }
+
+    pub fn record(&self, report: BenchmarkReport) {
+        if let Some(log) = &self.log {
+            let written = serde_json::to_string(&report)
+                .map_err(io::Error::from)
+                .and_then(|line| {
+                    let mut file = log.lock().expect("benchmark log poisoned");
+                    writeln!(file, "{line}")?;
+                    file.sync_data()
+                });
+            if let Err(e) = written {
+                tracing::error!("Failed to store benchmark {}: {}", report.benchmark_id, e);
+            }
+        }
+        self.lock().push(report);
+    }
+
+    /// Benchmarks of `program_id`, of `version` when given, on the host with the hardware
+    /// digest `hardware` that both executed and proved, by their medians
+    pub fn observations(
+        &self,
+        program_id: &ProgramID,
+        version: Option<&str>,
+        hardware: &str,
+    ) -> Vec<Observation> {
+        self.lock()
+            .iter()
+            .filter(|report| {
+                &report.program_id == program_id
+                    && version.is_none_or(|version| report.version == version)
+                    && report.hardware.digest == hardware
+            })
+            .filter_map(|report| {
+                let (execute, prove) = (report.execute.as_ref()?, report.prove.as_ref()?);
+                Some(Observation {
+                    cycles: execute.cycles.median,
+                    proving_time_milliseconds: prove.proving_time_milliseconds.median,
+                    peak_memory_bytes: prove.peak_memory_bytes.as_ref().map(|stats| stats.median),
+                })
+            })
+            .collect()
+    }
+
+    /// Benchmarks matching `filter`, newest first
+    pub fn list(&self, filter: &BenchmarkFilter, limit: usize) -> Vec<BenchmarkReport> {
+        self.lock()
+            .iter()
+            .rev()
+            .filter(|report| filter.matches(report))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Check the latest benchmark of every series matching `filter` against its baseline.
+    ///
+    /// The `version` of the filter selects the series whose latest benchmark ran that version;
+    /// the baseline is made of the benchmarks before it, whatever version they ran.
+    pub fn regressions(
+        &self,
+        filter: &BenchmarkFilter,
+        policy: RegressionPolicy,
+    ) -> RegressionReport {
+        let series_filter = BenchmarkFilter {
+            version: None,
+            ..filter.clone()
+        };
+        let reports = self.lock();
+        let mut series: Vec<Vec<&BenchmarkReport>> = Vec::new();
+        for report in reports
+            .iter()
+            .filter(|report| series_filter.matches(report))
+        {
+            let same_series = |other: &&BenchmarkReport| {
+                other.program_id == report.program_id
+                    && other.input_hash == report.input_hash
+                    && other.hardware.digest == report.hardware.digest
+            };
+            match series.iter_mut().find(|runs| same_series(&runs[0])) {
+                Some(runs) => runs.push(report),
+                None => series.push(vec![report]),
+            }
+        }
+
+        let mut checks: Vec<SeriesCheck> = series
+            .into_iter()
+            .filter(|runs| filter.matches(runs[runs.len() - 1]))
+            .filter_map(|runs| check_series(&runs, policy))
+            .collect();
+        // Stable, so series keep the order of their first benchmark
+        checks.sort_by_key(|check| !check.regressed);
+
+        RegressionReport {
+            threshold_percent: policy.threshold_percent,
+            baseline_runs: policy.baseline_runs,
+            regressed: checks.iter().any(|check| check.regressed),
+            series: checks,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<BenchmarkReport>> {
+        self.reports.lock().expect("benchmark history poisoned")
+    }
+}
+
+/// Check the last of `runs`, oldest first, against up to `baseline_runs` of the ones before it
+fn check_series(runs: &[&BenchmarkReport], policy: RegressionPolicy) -> Option<SeriesCheck> {
+    let (latest, previous) = runs.split_last()?;
+    let metrics: Vec<MetricCheck> = Metric::ALL
+        .into_iter()
+        .filter_map(|metric| {
+            let value = metric.of(latest)?;
+            let baseline: Vec<(&BenchmarkReport, f64)> = previous
+                .iter()
+                .rev()
+                .filter_map(|report| Some((*report, metric.of(report)?)))
+                .take(policy.baseline_runs)
+                .collect();
+            if baseline.is_empty() {
+                return None;
+            }
+            let mean = baseline.iter().map(|(_, value)| value).sum::<f64>() / baseline.len() as f64;
+            let change_percent = if mean > 0.0 {
+                (value - mean) / mean * 100.0
+            } else {
+                0.0
+            };
+
+            let mut baseline_versions = Vec::new();
+            for (report, _) in baseline.iter().rev() {
+                if !baseline_versions.contains(&report.version) {
+                    baseline_versions.push(report.version.clone());
+                }
+            }
+            Some(MetricCheck {
+                metric,
+                baseline: mean,
+                latest: value,
+                change_percent,
+                regressed: change_percent > policy.threshold_percent,
+                baseline_benchmarks: baseline
+                    .iter()
+                    .rev()
+                    .map(|(report, _)| report.benchmark_id)
+                    .collect(),
+                baseline_versions,
+            })
+        })
+        .collect();
+    if metrics.is_empty() {
+        return None;
+    }
+
+    Some(SeriesCheck {
+        program_id: latest.program_id.clone(),
+        input_hash: latest.input_hash.clone(),
+        hardware: latest.hardware.digest.clone(),
+        benchmark_id: latest.benchmark_id,
+        version: latest.version.clone(),
+        regressed: metrics.iter().any(|metric| metric.regressed),
+        metrics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::{BenchmarkMode, ExecuteStatistics, SCHEMA_VERSION, Statistics};
+    use crate::endpoints::info::hardware_fingerprint;
+    use tempfile::TempDir;
+
+    fn report(version: &str, input_hash: &str, cycles: f64) -> BenchmarkReport {
+        BenchmarkReport {
+            schema_version: SCHEMA_VERSION,
+            benchmark_id: Uuid::new_v4(),
+            program_id: ProgramID("sp1".to_string()),
+            version: version.to_string(),
+            input_hash: input_hash.to_string(),
+            mode: BenchmarkMode::Execute,
+            warmup: 0,
+            runs: 1,
+            started_at: 0,
+            finished_at: 0,
+            hardware: hardware_fingerprint(),
+            execute: Some(ExecuteStatistics {
+                wall_time_milliseconds: Statistics::new(vec![1.0]),
+                cycles: Statistics::new(vec![cycles]),
+            }),
+            prove: None,
+        }
+    }
+
+    fn policy() -> RegressionPolicy {
+        RegressionPolicy {
+            threshold_percent: 5.0,
+            baseline_runs: 2,
+        }
+    }
+
+    #[test]
+    fn test_regressions() {
+        let history = BenchmarkHistory::new(policy());
+        // The oldest run falls out of the baseline
+        history.record(report("v1", "a", 50.0));
+        history.record(report("v1", "a", 100.0));
+        history.record(report("v1", "a", 100.0));
+        history.record(report("v2", "a", 110.0));
+        // Within the threshold
+        history.record(report("v1", "b", 100.0));
+        history.record(report("v2", "b", 104.0));
+        // No baseline yet
+        history.record(report("v2", "c", 100.0));
+
+        let report = history.regressions(&BenchmarkFilter::default(), policy());
+        assert!(report.regressed);
+        assert_eq!(report.series.len(), 2);
+
+        let series = &report.series[0];
+        assert!(series.regressed);
+        assert_eq!(
+            (series.input_hash.as_str(), series.version.as_str()),
+            ("a", "v2")
+        );
+        let cycles = &series.metrics[0];
+        assert_eq!(cycles.metric, Metric::Cycles);
+        assert_eq!(cycles.baseline, 100.0);
+        assert_eq!(cycles.change_percent, 10.0);
+        assert_eq!(cycles.baseline_benchmarks.len(), 2);
+        assert_eq!(cycles.baseline_versions, ["v1"]);
+        assert!(!report.series[1].regressed);
+
+        // A looser threshold
+        let report = history.regressions(
+            &BenchmarkFilter::default(),
+            RegressionPolicy {
+                threshold_percent: 15.0,
+                ..policy()
+            },
+        );
+        assert!(!report.regressed);
+
+        let filter = BenchmarkFilter {
+            input_hash: Some("b".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(history.regressions(&filter, policy()).series.len(), 1);
+        assert_eq!(history.list(&filter, 10).len(), 2);
+        assert_eq!(history.list(&filter, 10)[0].version, "v2");
+
+        // A version selects the series it is the latest of, compared against the versions
+        // before it
+        let filter = BenchmarkFilter {
+            version: Some("v2".to_string()),
+            ..Default::default()
+        };
+        let report = history.regressions(&filter, policy());
+        assert_eq!(report.series.len(), 2);
+        assert_eq!(report.series[0].metrics[0].baseline_versions, ["v1"]);
+        let filter = BenchmarkFilter {
+            version: Some("v1".to_string()),
+            ..Default::default()
+        };
+        assert!(history.regressions(&filter, policy()).series.is_empty());
+    }
+
+    #[test]
+    fn test_history_survives_restart() {
+        let dir = TempDir::new().unwrap();
+        {
+            let history = BenchmarkHistory::open(dir.path(), policy()).unwrap();
+            history.record(report("v1", "a", 100.0));
+            history.record(report("v2", "a", 120.0));
+        }
+        // A torn line from a crash
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(LOG_FILE))
+            .unwrap();
+        write!(file, "{{\"schema_version\":").unwrap();
+
+        let history = BenchmarkHistory::open(dir.path(), policy()).unwrap();
+        assert_eq!(history.list(&BenchmarkFilter::default(), 10).len(), 2);
+        assert!(
+            history
+                .regressions(&BenchmarkFilter::default(), policy())
+                .regressed
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Peak resident memory of the server process while a proof runs.
+//!
+//! The backends prove in-process, so the resident set of the server is what a proof needs,
+//! plus what the server itself holds and what concurrent proofs use.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use sysinfo::{Pid, System};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Samples the resident memory of the process on a background thread until stopped
+pub struct MemorySampler {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<u64>>,
+}
+
+impl MemorySampler {
+    pub fn start() -> Self {
+        let Ok(pid) = sysinfo::get_current_pid() else {
+            return Self {
+                stop: Arc::default(),
+                thread: None,
+            };
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut system = System::new();
+            let mut peak = resident_memory(&mut system, pid);
+            while !stopped.load(Ordering::Relaxed) {
+                std::thread::sleep(SAMPLE_INTERVAL);
+                peak = peak.max(resident_memory(&mut system, pid));
+            }
+            peak
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop sampling, returning the peak in bytes, or `None` when the platform does not
+    /// report the memory of a process
+    pub fn stop(mut self) -> Option<u64> {
+        self.stop.store(true, Ordering::Relaxed);
+        let peak = self.thread.take()?.join().ok()?;
+        (peak > 0).then_some(peak)
+    }
+}
+
+fn resident_memory(system: &mut System, pid: Pid) -> u64 {
+    system.refresh_process(pid);
+    system.process(pid).map_or(0, |process| process.memory())
+}
+
+impl Drop for MemorySampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
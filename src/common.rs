@@ -1,8 +1,27 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{StatusCode, request::Parts},
+};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    sync::Arc,
+};
 use tokio::sync::RwLock;
 use zkvm_interface::zkVM;
 
+use crate::benchmark::BenchmarkHistory;
+use crate::config::{BenchmarkLimits, Config};
+use crate::jobs::JobStore;
+use crate::lifecycle::Lifecycle;
+use crate::program::{
+    BlockValidator, InputError, InputKind, NativeValidation, ProgramInput, ValidationOutcome,
+    reth_validation, validate_natively,
+};
+use crate::reload::Reloader;
+use crate::scheduler::Scheduler;
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
 #[serde(transparent)]
 pub struct ProgramID(pub String);
@@ -15,12 +34,65 @@ impl From<zkVMVendor> for ProgramID {
     }
 }
 
+/// Identity of the client that submitted a request, taken from the
+/// `x-client-id` header. Used to share the prover fairly between clients.
+///
+/// The header is not authenticated, so a client can claim any identity, or several of them
+/// to get a larger share. Deployments that need the fairness to hold should set the header in
+/// an authenticating proxy in front of the server, overwriting what clients send.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
+#[serde(transparent)]
+pub struct ClientID(pub String);
+
+impl ClientID {
+    pub const HEADER: &'static str = "x-client-id";
+}
+
+impl Default for ClientID {
+    fn default() -> Self {
+        ClientID("anonymous".to_string())
+    }
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientID {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let client_id = parts
+            .headers
+            .get(Self::HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| ClientID(value.to_string()))
+            .unwrap_or_default();
+        Ok(client_id)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[allow(non_camel_case_types)]
 pub enum zkVMVendor {
     Risc0,
     SP1,
+    /// Fake backend for developing against the HTTP API, see `mock_zkvm`
+    Mock,
+}
+
+impl zkVMVendor {
+    pub const ALL: [zkVMVendor; 3] = [zkVMVendor::Risc0, zkVMVendor::SP1, zkVMVendor::Mock];
+
+    /// Whether the backend was enabled through its cargo feature
+    pub fn is_compiled(self) -> bool {
+        match self {
+            // No Ere backend is wired up for risc0 yet
+            zkVMVendor::Risc0 => false,
+            zkVMVendor::SP1 => cfg!(feature = "sp1"),
+            zkVMVendor::Mock => cfg!(feature = "mock"),
+        }
+    }
 }
 
 impl std::str::FromStr for zkVMVendor {
@@ -30,8 +102,9 @@ impl std::str::FromStr for zkVMVendor {
         match s.to_lowercase().as_str() {
             "risc0" => Ok(zkVMVendor::Risc0),
             "sp1" => Ok(zkVMVendor::SP1),
+            "mock" => Ok(zkVMVendor::Mock),
             _ => Err(format!(
-                "Unsupported zkVM type: {}. Supported types are: risc0, sp1",
+                "Unsupported zkVM type: {}. Supported types are: risc0, sp1, mock",
                 s
             )),
         }
@@ -43,26 +116,329 @@ impl std::fmt::Display for zkVMVendor {
         match self {
             zkVMVendor::Risc0 => write!(f, "risc0"),
             zkVMVendor::SP1 => write!(f, "sp1"),
+            zkVMVendor::Mock => write!(f, "mock"),
         }
     }
 }
 
+/// Reads the public values the guest committed out of a proof
+pub type PublicValuesReader = fn(&[u8]) -> anyhow::Result<Vec<u8>>;
+
 #[derive(Clone)]
 #[allow(non_camel_case_types)]
 pub struct zkVMInstance {
     pub vendor: zkVMVendor,
     pub vm: Arc<dyn zkVM + Send + Sync>,
+    /// The ELF the VM was set up with, when known
+    pub elf: Option<Arc<[u8]>>,
+    /// How request inputs are decoded for this program
+    pub input: InputKind,
+    /// How to read the public values back from a proof of `vm`, when the backend supports it
+    pub public_values: Option<PublicValuesReader>,
 }
 
 impl zkVMInstance {
     pub fn new(vendor: zkVMVendor, vm: Arc<dyn zkVM + Send + Sync>) -> Self {
-        Self { vendor, vm }
+        Self {
+            vendor,
+            vm,
+            elf: None,
+            input: InputKind::default(),
+            public_values: None,
+        }
+    }
+
+    pub fn with_input(mut self, input: InputKind) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn with_elf(mut self, elf: Vec<u8>) -> Self {
+        self.elf = Some(elf.into());
+        self
+    }
+
+    pub fn with_public_values(mut self, read: PublicValuesReader) -> Self {
+        self.public_values = Some(read);
+        self
+    }
+}
+
+/// Registered program versions, and the aliases requests use to refer to them.
+///
+/// A version is identified by the hex encoded SHA-256 of its ELF.
+#[derive(Clone, Default)]
+pub struct Programs {
+    versions: HashMap<String, zkVMInstance>,
+    aliases: HashMap<ProgramID, String>,
+    // Every registered version each alias has pointed at, which requests may pin
+    served: HashMap<ProgramID, HashSet<String>>,
+}
+
+impl Programs {
+    pub fn add_version(&mut self, version: String, instance: zkVMInstance) {
+        self.versions.insert(version, instance);
+    }
+
+    /// Point `alias` at `version`. Returns false if the version is not registered.
+    pub fn set_alias(&mut self, alias: ProgramID, version: &str) -> bool {
+        if !self.versions.contains_key(version) {
+            return false;
+        }
+        self.served
+            .entry(alias.clone())
+            .or_default()
+            .insert(version.to_string());
+        self.aliases.insert(alias, version.to_string());
+        true
+    }
+
+    /// Whether `alias` points at `version` now or pointed at it before
+    pub fn has_served(&self, alias: &ProgramID, version: &str) -> bool {
+        self.served
+            .get(alias)
+            .is_some_and(|versions| versions.contains(version))
+    }
+
+    /// Keep the versions the aliases of `previous` pointed at, for those still registered
+    pub fn inherit_served(&mut self, previous: &Programs) {
+        for (alias, versions) in &previous.served {
+            let registered = versions.iter().filter(|v| self.versions.contains_key(*v));
+            self.served
+                .entry(alias.clone())
+                .or_default()
+                .extend(registered.cloned());
+        }
+    }
+
+    /// Unregister `version`, unless an alias points at it. Returns whether it was removed.
+    pub fn remove_version(&mut self, version: &str) -> bool {
+        if self.aliases.values().any(|aliased| aliased == version) {
+            return false;
+        }
+        for versions in self.served.values_mut() {
+            versions.remove(version);
+        }
+        self.versions.remove(version).is_some()
+    }
+
+    pub fn has_version(&self, version: &str) -> bool {
+        self.versions.contains_key(version)
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = (&String, &zkVMInstance)> {
+        self.versions.iter()
+    }
+
+    pub fn aliases(&self) -> &HashMap<ProgramID, String> {
+        &self.aliases
+    }
+
+    /// Register `instance` as a version named after `program_id`, with `program_id`
+    /// as its alias
+    #[cfg(test)]
+    pub fn insert(&mut self, program_id: ProgramID, instance: zkVMInstance) {
+        self.add_version(program_id.0.clone(), instance);
+        self.set_alias(program_id.clone(), &program_id.0);
+    }
+}
+
+/// A program as resolved for one request
+#[derive(Clone)]
+pub struct ResolvedProgram {
+    pub version: String,
+    pub instance: zkVMInstance,
+}
+
+impl ResolvedProgram {
+    /// Decode the `input` of a request according to the program's input kind, and run the
+    /// pre-flight checks on it
+    pub fn decode_input(
+        &self,
+        input: &serde_json::Value,
+    ) -> Result<ProgramInput, (StatusCode, String)> {
+        let invalid = |errors: Vec<InputError>| {
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "Invalid {} input: {}",
+                    self.instance.input,
+                    errors.join("; ")
+                ),
+            )
+        };
+        let input =
+            ProgramInput::decode(self.instance.input, input).map_err(|e| invalid(vec![e]))?;
+        let errors = input.validate();
+        if !errors.is_empty() {
+            return Err(invalid(errors));
+        }
+        Ok(input)
+    }
+
+    /// The outcome the guest committed in `proof`, for programs taking blocks. `None` when the
+    /// backend cannot read public values back, or the guest committed none.
+    pub fn committed_outcome(&self, proof: &[u8]) -> Result<Option<ValidationOutcome>, String> {
+        let Some(read) = self.instance.public_values else {
+            return Ok(None);
+        };
+        if self.instance.input != InputKind::ClientInput {
+            return Ok(None);
+        }
+        let public_values =
+            read(proof).map_err(|e| format!("Failed to read public values: {e}"))?;
+        if public_values.is_empty() {
+            return Ok(None);
+        }
+        ValidationOutcome::from_public_values(&public_values)
+            .map(Some)
+            .map_err(|e| format!("Failed to decode the committed outcome: {e}"))
     }
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    pub programs: Arc<RwLock<HashMap<ProgramID, zkVMInstance>>>,
+    pub programs: Arc<RwLock<Programs>>,
+    pub scheduler: Arc<Scheduler>,
+    pub jobs: Arc<JobStore>,
+    pub lifecycle: Arc<Lifecycle>,
+    pub reloader: Arc<Reloader>,
+    pub benchmarks: Arc<BenchmarkHistory>,
+    /// Run the blocks of an input on the host before handing them to a guest
+    pub native_validation: bool,
+    /// Reject blocks that fail stateless validation instead of executing or proving them
+    pub reject_invalid_blocks: bool,
+    /// Validates blocks natively, reth's `stateless_validation` outside tests
+    pub block_validator: BlockValidator,
+    pub benchmark_limits: BenchmarkLimits,
+    /// Token the admin routes require, `None` to disable them
+    pub admin_token: Option<Arc<str>>,
+}
+
+impl AppState {
+    pub fn new(config: &Config) -> Self {
+        let scheduler = Arc::new(Scheduler::new(config.max_concurrent_proofs));
+        Self {
+            programs: Arc::new(RwLock::new(Programs::default())),
+            lifecycle: Arc::new(Lifecycle::new(scheduler.clone())),
+            scheduler,
+            jobs: Arc::new(JobStore::new(config.job_history_limit)),
+            reloader: Arc::new(Reloader::new(config)),
+            benchmarks: Arc::new(BenchmarkHistory::new(config.regression)),
+            native_validation: config.native_validation,
+            reject_invalid_blocks: config.reject_invalid_blocks,
+            block_validator: reth_validation,
+            benchmark_limits: config.benchmark_limits,
+            admin_token: config.admin_token.as_deref().map(Arc::from),
+        }
+    }
+
+    /// Use `jobs` instead of an in-memory job store
+    pub fn with_jobs(mut self, jobs: JobStore) -> Self {
+        self.jobs = Arc::new(jobs);
+        self
+    }
+
+    /// Validate blocks natively with `validator` instead of reth
+    #[cfg(test)]
+    pub fn with_block_validator(mut self, validator: BlockValidator) -> Self {
+        self.block_validator = validator;
+        self
+    }
+
+    /// Use `benchmarks` instead of an in-memory benchmark history
+    pub fn with_benchmarks(mut self, benchmarks: BenchmarkHistory) -> Self {
+        self.benchmarks = Arc::new(benchmarks);
+        self
+    }
+
+    /// Validate the blocks of an input natively, when `native_validation` or
+    /// `reject_invalid_blocks` is set. With `reject_invalid_blocks`, an invalid block is
+    /// rejected before it reaches a guest.
+    pub async fn pre_execute(
+        &self,
+        input: &ProgramInput,
+    ) -> Result<Option<NativeValidation>, (StatusCode, String)> {
+        if !self.native_validation && !self.reject_invalid_blocks {
+            return Ok(None);
+        }
+        let native = self.validate_natively(input).await?;
+        if let Some(reason) = native
+            .as_ref()
+            .and_then(|native| native.outcome.reason.as_ref())
+            .filter(|_| self.reject_invalid_blocks)
+        {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "Block {} failed stateless validation: {}",
+                    reason.block_number, reason.message
+                ),
+            ));
+        }
+        Ok(native)
+    }
+
+    /// Run the stateless validation of the blocks of an input on the host
+    pub async fn validate_natively(
+        &self,
+        input: &ProgramInput,
+    ) -> Result<Option<NativeValidation>, (StatusCode, String)> {
+        let Some((blocks, chain)) = input.blocks() else {
+            return Ok(None);
+        };
+
+        let (blocks, chain, validate) = (blocks.to_vec(), chain.clone(), self.block_validator);
+        let (outcome, report) =
+            tokio::task::spawn_blocking(move || validate_natively(&blocks, &chain, validate))
+                .await
+                .map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Native validation task failed: {}", e),
+                    )
+                })?
+                .map_err(|e| {
+                    (
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        format!("Invalid input: {e}"),
+                    )
+                })?;
+        Ok(Some(NativeValidation { outcome, report }))
+    }
+
+    /// Look up the version `program_id` points at, or the pinned `version`, which must be one
+    /// `program_id` points at or pointed at before.
+    ///
+    /// The instance is cloned, so the programs lock is not held while it runs and
+    /// a reload or repointed alias does not affect requests already running.
+    pub async fn resolve(
+        &self,
+        program_id: &ProgramID,
+        version: Option<&str>,
+    ) -> Result<ResolvedProgram, (StatusCode, String)> {
+        let programs = self.programs.read().await;
+        let aliased = programs
+            .aliases
+            .get(program_id)
+            .ok_or((StatusCode::NOT_FOUND, "Program not found".to_string()))?;
+        if version.is_some_and(|version| !programs.has_served(program_id, version)) {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Version not found for program {}", program_id.0),
+            ));
+        }
+        let version = version.unwrap_or(aliased);
+        let instance = programs
+            .versions
+            .get(version)
+            .ok_or((StatusCode::NOT_FOUND, "Version not found".to_string()))?;
+        Ok(ResolvedProgram {
+            version: version.to_string(),
+            instance: instance.clone(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -70,10 +446,57 @@ mod tests {
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_resolve_alias_and_pinned_version() {
+        use crate::config::Config;
+        use crate::mock_zkvm::MockZkVM;
+
+        let state = AppState::new(&Config::default());
+        let alias = ProgramID("stateless".to_string());
+        {
+            let mut programs = state.programs.write().await;
+            for version in ["v1", "v2", "other-v1"] {
+                programs.add_version(
+                    version.to_string(),
+                    zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+                );
+            }
+            assert!(programs.set_alias(alias.clone(), "v1"));
+            assert!(!programs.set_alias(alias.clone(), "v3"));
+            assert!(programs.set_alias(ProgramID("other".to_string()), "other-v1"));
+        }
+
+        // The version of another program cannot be run under this one
+        let (status, _) = state.resolve(&alias, Some("other-v1")).await.err().unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        assert_eq!(state.resolve(&alias, None).await.unwrap().version, "v1");
+        // A version the alias never pointed at cannot be pinned
+        let (status, _) = state.resolve(&alias, Some("v2")).await.err().unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        state.programs.write().await.set_alias(alias.clone(), "v2");
+        assert_eq!(state.resolve(&alias, None).await.unwrap().version, "v2");
+        assert_eq!(
+            state.resolve(&alias, Some("v1")).await.unwrap().version,
+            "v1"
+        );
+
+        let (status, _) = state.resolve(&alias, Some("v3")).await.err().unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, message) = state
+            .resolve(&ProgramID("unknown".to_string()), None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(message, "Program not found");
+    }
+
     #[test]
     fn test_zkvm_type_parsing() {
         assert_eq!("risc0".parse::<zkVMVendor>().unwrap(), zkVMVendor::Risc0);
         assert_eq!("sp1".parse::<zkVMVendor>().unwrap(), zkVMVendor::SP1);
+        assert_eq!("mock".parse::<zkVMVendor>().unwrap(), zkVMVendor::Mock);
         assert!("invalid".parse::<zkVMVendor>().is_err());
         assert!("".parse::<zkVMVendor>().is_err());
     }
@@ -0,0 +1,324 @@
+//! Server configuration, read from `POOST_*` environment variables at startup.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::common::zkVMVendor;
+use crate::program::TrustPolicy;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of proofs that may run at the same time.
+    /// Additional `/prove` requests wait in the scheduler queue.
+    pub max_concurrent_proofs: usize,
+    /// Number of finished jobs kept in the `/jobs` history
+    pub job_history_limit: usize,
+    /// Backends whose built-in program is registered at startup
+    pub backends: Vec<zkVMVendor>,
+    /// Prebuilt SP1 guest, either the ELF or a directory holding `sp1-program.elf`,
+    /// used instead of the ELF embedded at build time
+    #[cfg_attr(not(feature = "sp1"), allow(dead_code))]
+    pub sp1_elf: Option<PathBuf>,
+    /// Expected SHA-256 of the SP1 guest ELF
+    #[cfg_attr(not(feature = "sp1"), allow(dead_code))]
+    pub sp1_elf_digest: Option<String>,
+    /// Behavior of the `mock` backend
+    pub mock: MockConfig,
+    /// Run the stateless validation of blocks on the host before executing or proving them
+    pub native_validation: bool,
+    /// Reject `ClientInput` blocks that fail stateless validation on the host, instead of
+    /// executing or proving them
+    pub reject_invalid_blocks: bool,
+    /// Directory scanned for program manifests at startup
+    pub programs_dir: PathBuf,
+    /// Reload the programs when files in `programs_dir` change
+    pub watch_programs: bool,
+    /// Which ELFs may be loaded
+    pub elf_trust: TrustPolicy,
+    /// Directory holding the persistent job log, accepted requests and proofs
+    pub data_dir: PathBuf,
+    /// What to do at startup with proofs that were interrupted by a restart
+    pub recovery_policy: RecoveryPolicy,
+    /// How long a shutdown waits for running executions and proofs to finish
+    pub drain_timeout: Duration,
+    /// What happens to work that is unfinished when the drain ends
+    pub drain_leftovers: LeftoverPolicy,
+    /// When a benchmark counts as a regression
+    pub regression: RegressionPolicy,
+    /// Largest `/benchmark` requests accepted
+    pub benchmark_limits: BenchmarkLimits,
+    /// How long a version dropped from the manifests stays loaded once no unfinished job
+    /// refers to it
+    pub retired_version_ttl: Duration,
+    /// Bearer token the `/admin` routes require; without one they are disabled
+    pub admin_token: Option<String>,
+}
+
+/// Knobs of the mock zkVM, so integration tests can exercise slow and failing backends
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(not(any(test, feature = "mock")), allow(dead_code))]
+pub struct MockConfig {
+    /// Cycles reported for an empty input
+    pub base_cycles: u64,
+    /// Cycles added per byte of serialized input
+    pub cycles_per_byte: u64,
+    pub execute_latency: Duration,
+    pub prove_latency: Duration,
+    /// Probability in `[0, 1]` that an execution or proof fails
+    pub failure_rate: f64,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            base_cycles: 100,
+            cycles_per_byte: 1,
+            // Avoids 0-ms durations in unit tests
+            execute_latency: Duration::from_millis(1),
+            prove_latency: Duration::from_millis(1),
+            failure_rate: 0.0,
+        }
+    }
+}
+
+/// When the latest benchmark of an input counts as a regression of the ones before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionPolicy {
+    /// Increase over the baseline, in percent, above which a metric regressed
+    pub threshold_percent: f64,
+    /// Number of previous benchmarks averaged into the baseline
+    pub baseline_runs: usize,
+}
+
+impl Default for RegressionPolicy {
+    fn default() -> Self {
+        Self {
+            threshold_percent: 5.0,
+            baseline_runs: 5,
+        }
+    }
+}
+
+/// Largest number of runs a single benchmark may ask for, since it holds a proving slot
+/// throughout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchmarkLimits {
+    pub max_runs: usize,
+    pub max_warmup: usize,
+}
+
+impl Default for BenchmarkLimits {
+    fn default() -> Self {
+        Self {
+            max_runs: 100,
+            max_warmup: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Put interrupted proofs back in the queue
+    Resume,
+    /// Mark interrupted proofs as failed
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeftoverPolicy {
+    /// Keep unfinished jobs in the job log, to be handled by the recovery policy
+    Persist,
+    /// Mark unfinished jobs as cancelled
+    Cancel,
+}
+
+impl FromStr for LeftoverPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "persist" => Ok(LeftoverPolicy::Persist),
+            "cancel" => Ok(LeftoverPolicy::Cancel),
+            _ => Err(format!(
+                "Unsupported leftover policy: {}. Supported policies are: persist, cancel",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for RecoveryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "resume" => Ok(RecoveryPolicy::Resume),
+            "fail" => Ok(RecoveryPolicy::Fail),
+            _ => Err(format!(
+                "Unsupported recovery policy: {}. Supported policies are: resume, fail",
+                s
+            )),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_concurrent_proofs: 1,
+            job_history_limit: 10_000,
+            // Every compiled backend except the mock, which has to be asked for
+            backends: zkVMVendor::ALL
+                .into_iter()
+                .filter(|vendor| vendor.is_compiled() && *vendor != zkVMVendor::Mock)
+                .collect(),
+            sp1_elf: None,
+            sp1_elf_digest: None,
+            mock: MockConfig::default(),
+            native_validation: false,
+            reject_invalid_blocks: false,
+            programs_dir: PathBuf::from("programs"),
+            watch_programs: true,
+            elf_trust: TrustPolicy::default(),
+            data_dir: PathBuf::from("data"),
+            recovery_policy: RecoveryPolicy::Resume,
+            drain_timeout: Duration::from_secs(300),
+            drain_leftovers: LeftoverPolicy::Persist,
+            regression: RegressionPolicy::default(),
+            benchmark_limits: BenchmarkLimits::default(),
+            retired_version_ttl: Duration::from_secs(3600),
+            admin_token: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Config::default();
+        let config = Self {
+            max_concurrent_proofs: env_or(
+                "POOST_MAX_CONCURRENT_PROOFS",
+                defaults.max_concurrent_proofs,
+            )?,
+            job_history_limit: env_or("POOST_JOB_HISTORY_LIMIT", defaults.job_history_limit)?,
+            backends: match std::env::var("POOST_BACKENDS") {
+                Ok(_) => env_list("POOST_BACKENDS")
+                    .iter()
+                    .map(|vendor| vendor.parse().map_err(|e: String| anyhow::anyhow!(e)))
+                    .collect::<anyhow::Result<_>>()?,
+                Err(_) => defaults.backends,
+            },
+            sp1_elf: std::env::var("POOST_SP1_ELF").ok().map(PathBuf::from),
+            sp1_elf_digest: std::env::var("POOST_SP1_ELF_DIGEST").ok(),
+            mock: MockConfig {
+                base_cycles: env_or("POOST_MOCK_BASE_CYCLES", defaults.mock.base_cycles)?,
+                cycles_per_byte: env_or(
+                    "POOST_MOCK_CYCLES_PER_BYTE",
+                    defaults.mock.cycles_per_byte,
+                )?,
+                execute_latency: Duration::from_millis(env_or(
+                    "POOST_MOCK_EXECUTE_LATENCY_MS",
+                    defaults.mock.execute_latency.as_millis() as u64,
+                )?),
+                prove_latency: Duration::from_millis(env_or(
+                    "POOST_MOCK_PROVE_LATENCY_MS",
+                    defaults.mock.prove_latency.as_millis() as u64,
+                )?),
+                failure_rate: env_or("POOST_MOCK_FAILURE_RATE", defaults.mock.failure_rate)?,
+            },
+            native_validation: env_or("POOST_NATIVE_VALIDATION", defaults.native_validation)?,
+            reject_invalid_blocks: env_or(
+                "POOST_REJECT_INVALID_BLOCKS",
+                defaults.reject_invalid_blocks,
+            )?,
+            programs_dir: env_or("POOST_PROGRAMS_DIR", defaults.programs_dir)?,
+            watch_programs: env_or("POOST_WATCH_PROGRAMS", defaults.watch_programs)?,
+            elf_trust: TrustPolicy::new(
+                &env_list("POOST_ELF_ALLOWLIST"),
+                &env_list("POOST_TRUSTED_ELF_KEYS"),
+            )?,
+            data_dir: env_or("POOST_DATA_DIR", defaults.data_dir)?,
+            recovery_policy: env_or("POOST_RECOVERY_POLICY", defaults.recovery_policy)?,
+            drain_timeout: Duration::from_secs(env_or(
+                "POOST_DRAIN_TIMEOUT_SECS",
+                defaults.drain_timeout.as_secs(),
+            )?),
+            drain_leftovers: env_or("POOST_DRAIN_LEFTOVERS", defaults.drain_leftovers)?,
+            regression: RegressionPolicy {
+                threshold_percent: env_or(
+                    "POOST_REGRESSION_THRESHOLD_PERCENT",
+                    defaults.regression.threshold_percent,
+                )?,
+                baseline_runs: env_or(
+                    "POOST_REGRESSION_BASELINE_RUNS",
+                    defaults.regression.baseline_runs,
+                )?,
+            },
+            benchmark_limits: BenchmarkLimits {
+                max_runs: env_or(
+                    "POOST_BENCHMARK_MAX_RUNS",
+                    defaults.benchmark_limits.max_runs,
+                )?,
+                max_warmup: env_or(
+                    "POOST_BENCHMARK_MAX_WARMUP",
+                    defaults.benchmark_limits.max_warmup,
+                )?,
+            },
+            retired_version_ttl: Duration::from_secs(env_or(
+                "POOST_RETIRED_VERSION_TTL_SECS",
+                defaults.retired_version_ttl.as_secs(),
+            )?),
+            admin_token: std::env::var("POOST_ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        };
+        if config.max_concurrent_proofs == 0 {
+            anyhow::bail!("POOST_MAX_CONCURRENT_PROOFS must be at least 1");
+        }
+        let threshold = config.regression.threshold_percent;
+        if threshold.is_nan() || threshold < 0.0 {
+            anyhow::bail!("POOST_REGRESSION_THRESHOLD_PERCENT must not be negative");
+        }
+        if config.regression.baseline_runs == 0 {
+            anyhow::bail!("POOST_REGRESSION_BASELINE_RUNS must be at least 1");
+        }
+        if config.benchmark_limits.max_runs == 0 {
+            anyhow::bail!("POOST_BENCHMARK_MAX_RUNS must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&config.mock.failure_rate) {
+            anyhow::bail!("POOST_MOCK_FAILURE_RATE must be between 0 and 1");
+        }
+        if let Some(vendor) = config.backends.iter().find(|vendor| !vendor.is_compiled()) {
+            anyhow::bail!(
+                "POOST_BACKENDS selects {vendor}, but this build does not include that backend"
+            );
+        }
+        Ok(config)
+    }
+}
+
+/// Parse the environment variable `key`, falling back to `default` when it is unset
+fn env_or<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid value for {key}: {e}")),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Comma separated values of the environment variable `key`, empty when it is unset
+fn env_list(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
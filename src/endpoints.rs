@@ -1,9 +1,25 @@
+pub mod admin;
+pub mod benchmark;
+pub mod compare;
+pub mod estimate;
 pub mod execute;
 pub mod info;
+pub mod jobs;
+pub mod programs;
 pub mod prove;
+pub mod ready;
+pub mod validate;
 pub mod verify;
 
+pub use admin::{get_queue, get_status, reprioritize_job};
+pub use benchmark::run_benchmark;
+pub use compare::compare_executions;
+pub use estimate::estimate_proof;
 pub use execute::execute_program;
 pub use info::get_server_info;
+pub use jobs::{get_job, get_job_proof, list_jobs};
+pub use programs::{download_program_elf, get_program_elf};
 pub use prove::prove_program;
+pub use ready::get_readiness;
+pub use validate::validate_input;
 pub use verify::verify_proof;
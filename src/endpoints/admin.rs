@@ -0,0 +1,409 @@
+//! Operator endpoints for inspecting and steering the prover.
+
+use axum::{
+    Json,
+    extract::{FromRequestParts, Path, State},
+    http::{StatusCode, header::AUTHORIZATION, request::Parts},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::common::{AppState, ClientID, ProgramID, zkVMVendor};
+use crate::jobs::{JobKind, JobStatus, now_millis};
+use crate::lifecycle::Mode;
+use crate::program::InputKind;
+use crate::reload::ReloadEvent;
+use crate::scheduler::{Priority, QueuedJob};
+
+/// Guard of the admin routes: a request must carry `Authorization: Bearer <POOST_ADMIN_TOKEN>`.
+/// Without a configured token the admin routes are disabled.
+pub struct AdminAuth;
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(token) = &state.admin_token else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "The admin API is disabled, set POOST_ADMIN_TOKEN to enable it".to_string(),
+            ));
+        };
+        let given = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // Comparing digests takes the same time wherever the tokens differ
+        match given {
+            Some(given) if Sha256::digest(given) == Sha256::digest(token.as_bytes()) => {
+                Ok(AdminAuth)
+            }
+            _ => Err((
+                StatusCode::UNAUTHORIZED,
+                "Missing or invalid admin token".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueResponse {
+    pub running: usize,
+    pub waiting: Vec<QueuedJob>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReprioritizeRequest {
+    pub priority: Priority,
+}
+
+/// Execution or proof that is currently running
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunningJob {
+    pub job_id: Uuid,
+    pub kind: JobKind,
+    pub program_id: ProgramID,
+    pub client_id: ClientID,
+    pub input_hash: Option<String>,
+    pub elapsed_milliseconds: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub mode: Mode,
+    pub running: Vec<RunningJob>,
+    /// Proofs waiting for a slot
+    pub queued: usize,
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_status(State(state): State<AppState>) -> Json<StatusResponse> {
+    let now = now_millis();
+    let running = state
+        .jobs
+        .unfinished()
+        .into_iter()
+        .filter(|job| job.status == JobStatus::Running)
+        .map(|job| RunningJob {
+            job_id: job.job_id,
+            kind: job.kind,
+            program_id: job.program_id,
+            client_id: job.client_id,
+            input_hash: job.input_hash,
+            elapsed_milliseconds: now.saturating_sub(job.started_at.unwrap_or(now)),
+        })
+        .collect();
+
+    Json(StatusResponse {
+        mode: state.lifecycle.mode(),
+        running,
+        queued: state.scheduler.queued().len(),
+    })
+}
+
+/// Hold new executions and proofs, and stop starting waiting proofs, until resumed.
+/// Running work is not interrupted.
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn pause(
+    State(state): State<AppState>,
+) -> Result<Json<StatusResponse>, (StatusCode, String)> {
+    state.lifecycle.pause()?;
+    println!("Server paused");
+    Ok(get_status(State(state)).await)
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn resume(
+    State(state): State<AppState>,
+) -> Result<Json<StatusResponse>, (StatusCode, String)> {
+    state.lifecycle.resume()?;
+    println!("Server resumed");
+    Ok(get_status(State(state)).await)
+}
+
+/// Reject new executions and proofs while accepted ones run to completion
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn drain(
+    State(state): State<AppState>,
+) -> Result<Json<StatusResponse>, (StatusCode, String)> {
+    state.lifecycle.drain()?;
+    println!("Server draining");
+    Ok(get_status(State(state)).await)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProgramVersion {
+    /// SHA-256 of the ELF
+    pub version: String,
+    pub vendor: zkVMVendor,
+    /// Expected shape of the request `input`
+    pub input: InputKind,
+    /// Aliases currently pointing at this version
+    pub aliases: Vec<ProgramID>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetAliasRequest {
+    pub version: String,
+}
+
+/// Registered program versions and the aliases pointing at them
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_programs(State(state): State<AppState>) -> Json<Vec<ProgramVersion>> {
+    let programs = state.programs.read().await;
+    let mut versions: Vec<ProgramVersion> = programs
+        .versions()
+        .map(|(version, instance)| {
+            let mut aliases: Vec<ProgramID> = programs
+                .aliases()
+                .iter()
+                .filter(|(_, target)| *target == version)
+                .map(|(alias, _)| alias.clone())
+                .collect();
+            aliases.sort_by(|a, b| a.0.cmp(&b.0));
+            ProgramVersion {
+                version: version.clone(),
+                vendor: instance.vendor,
+                input: instance.input,
+                aliases,
+            }
+        })
+        .collect();
+    versions.sort_by(|a, b| {
+        a.aliases
+            .first()
+            .map(|a| &a.0)
+            .cmp(&b.aliases.first().map(|b| &b.0))
+    });
+    Json(versions)
+}
+
+/// Point an alias at a registered version, creating the alias if needed.
+/// Requests that already resolved the alias keep their version.
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn set_alias(
+    State(state): State<AppState>,
+    Path(alias): Path<ProgramID>,
+    Json(req): Json<SetAliasRequest>,
+) -> Result<Json<Vec<ProgramVersion>>, (StatusCode, String)> {
+    if !state
+        .reloader
+        .set_alias(&state.programs, alias, &req.version)
+        .await
+    {
+        return Err((StatusCode::NOT_FOUND, "Version not found".to_string()));
+    }
+
+    Ok(get_programs(State(state)).await)
+}
+
+/// Past reloads of the programs directory, oldest first
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_reloads(State(state): State<AppState>) -> Json<Vec<ReloadEvent>> {
+    Json(state.reloader.history())
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_queue(State(state): State<AppState>) -> Json<QueueResponse> {
+    Json(QueueResponse {
+        running: state.scheduler.running(),
+        waiting: state.scheduler.queued(),
+    })
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn reprioritize_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+    Json(req): Json<ReprioritizeRequest>,
+) -> Result<Json<QueueResponse>, (StatusCode, String)> {
+    if !state.scheduler.reprioritize(job_id, req.priority) {
+        return Err((StatusCode::NOT_FOUND, "Job is not waiting".to_string()));
+    }
+
+    Ok(get_queue(State(state)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::http::Request;
+    use std::sync::Arc;
+
+    async fn authorize(state: &AppState, header: Option<&str>) -> Result<(), StatusCode> {
+        let mut request = Request::builder();
+        if let Some(header) = header {
+            request = request.header(AUTHORIZATION, header);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        AdminAuth::from_request_parts(&mut parts, state)
+            .await
+            .map(|_| ())
+            .map_err(|(status, _)| status)
+    }
+
+    #[tokio::test]
+    async fn test_admin_auth() {
+        let state = AppState::new(&Config::default());
+        assert_eq!(
+            authorize(&state, Some("Bearer secret")).await,
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        let state = AppState::new(&Config {
+            admin_token: Some("secret".to_string()),
+            ..Config::default()
+        });
+        assert_eq!(authorize(&state, Some("Bearer secret")).await, Ok(()));
+        for header in [None, Some("Bearer wrong"), Some("secret"), Some("Bearer ")] {
+            assert_eq!(
+                authorize(&state, header).await,
+                Err(StatusCode::UNAUTHORIZED)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reprioritize_unknown_job() {
+        let state = AppState::new(&Config::default());
+
+        let result = reprioritize_job(
+            State(state),
+            Path(Uuid::new_v4()),
+            Json(ReprioritizeRequest {
+                priority: Priority::High,
+            }),
+        )
+        .await;
+
+        let (status, message) = result.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(message, "Job is not waiting");
+    }
+
+    #[tokio::test]
+    async fn test_get_queue_empty() {
+        let state = AppState::new(&Config::default());
+
+        let response = get_queue(State(state)).await.0;
+
+        assert_eq!(response.running, 0);
+        assert!(response.waiting.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_status_lists_running_jobs() {
+        let state = AppState::new(&Config::default());
+        let job = state.jobs.submit(
+            JobKind::Execute,
+            ProgramID("sp1".to_string()),
+            None,
+            ClientID::default(),
+            Some(1),
+            Some("abcd".to_string()),
+        );
+        state
+            .jobs
+            .submit(
+                JobKind::Execute,
+                ProgramID("sp1".to_string()),
+                None,
+                ClientID::default(),
+                Some(2),
+                None,
+            )
+            .executed(100);
+
+        let response = get_status(State(state)).await.0;
+
+        assert_eq!(response.mode, Mode::Running);
+        assert_eq!(response.queued, 0);
+        assert_eq!(response.running.len(), 1);
+        assert_eq!(response.running[0].job_id, job.job_id());
+        assert_eq!(response.running[0].input_hash.as_deref(), Some("abcd"));
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume_and_drain() {
+        let state = AppState::new(&Config::default());
+
+        let response = pause(State(state.clone())).await.unwrap().0;
+        assert_eq!(response.mode, Mode::Paused);
+
+        let response = resume(State(state.clone())).await.unwrap().0;
+        assert_eq!(response.mode, Mode::Running);
+
+        let response = drain(State(state.clone())).await.unwrap().0;
+        assert_eq!(response.mode, Mode::Draining);
+
+        // A drained server can be resumed, unlike one that is shutting down
+        let (status, _) = pause(State(state.clone())).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        let response = resume(State(state)).await.unwrap().0;
+        assert_eq!(response.mode, Mode::Running);
+    }
+
+    #[tokio::test]
+    async fn test_set_alias() {
+        use crate::common::zkVMInstance;
+        use crate::mock_zkvm::MockZkVM;
+
+        let state = AppState::new(&Config::default());
+        {
+            let mut programs = state.programs.write().await;
+            for id in ["v1", "v2"] {
+                programs.insert(
+                    ProgramID(id.to_string()),
+                    zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+                );
+            }
+        }
+
+        let versions = set_alias(
+            State(state.clone()),
+            Path(ProgramID("mainnet".to_string())),
+            Json(SetAliasRequest {
+                version: "v2".to_string(),
+            }),
+        )
+        .await
+        .unwrap()
+        .0;
+        let v2 = versions.iter().find(|v| v.version == "v2").unwrap();
+        assert_eq!(
+            v2.aliases,
+            vec![
+                ProgramID("mainnet".to_string()),
+                ProgramID("v2".to_string())
+            ]
+        );
+
+        let (status, _) = set_alias(
+            State(state),
+            Path(ProgramID("mainnet".to_string())),
+            Json(SetAliasRequest {
+                version: "v3".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
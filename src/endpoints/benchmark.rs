@@ -0,0 +1,523 @@
+//! Running a program repeatedly to get statistics instead of a single noisy measurement.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::instrument;
+use zkvm_interface::zkVM;
+
+use super::info::hardware_fingerprint;
+use crate::benchmark::{
+    BenchmarkFilter, BenchmarkMode, BenchmarkReport, ExecuteStatistics, MemorySampler,
+    ProveStatistics, RegressionReport, SCHEMA_VERSION, Statistics,
+};
+use crate::common::{AppState, ClientID, ProgramID};
+use crate::jobs::{JobKind, JobStatus, now_millis};
+use crate::lifecycle::Mode;
+use crate::scheduler::{Priority, Ticket};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BenchmarkRequest {
+    pub program_id: ProgramID,
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Decoded according to the input kind of the program
+    pub input: serde_json::Value,
+    #[serde(default)]
+    pub mode: BenchmarkMode,
+    /// Measured runs
+    #[serde(default = "default_runs")]
+    pub runs: usize,
+    /// Runs made before measuring
+    #[serde(default = "default_warmup")]
+    pub warmup: usize,
+    /// Where the benchmark is placed in the queue relative to waiting proofs
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+fn default_runs() -> usize {
+    5
+}
+
+fn default_warmup() -> usize {
+    1
+}
+
+/// Measurements of the runs that count
+#[derive(Default)]
+struct Samples {
+    execute_wall_time: Vec<f64>,
+    cycles: Vec<f64>,
+    prove_wall_time: Vec<f64>,
+    proving_time: Vec<f64>,
+    proof_size: Vec<f64>,
+    /// Empty when the platform does not report the memory of a process
+    peak_memory: Vec<f64>,
+}
+
+/// Why the runs of a benchmark stopped early
+enum Stopped {
+    Failed(String),
+    /// By a shutdown or a cancellation, between two runs
+    Interrupted(String),
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Execute and/or prove an input `warmup + runs` times, holding a proving slot throughout so
+/// that the runs do not share the machine with proofs
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn run_benchmark(
+    State(state): State<AppState>,
+    client_id: ClientID,
+    Json(req): Json<BenchmarkRequest>,
+) -> Result<Json<BenchmarkReport>, (StatusCode, String)> {
+    state.lifecycle.admit().await?;
+    let limits = state.benchmark_limits;
+    if req.runs == 0 || req.runs > limits.max_runs {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("runs must be between 1 and {}", limits.max_runs),
+        ));
+    }
+    if req.warmup > limits.max_warmup {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("warmup must be at most {}", limits.max_warmup),
+        ));
+    }
+
+    let program = state
+        .resolve(&req.program_id, req.version.as_deref())
+        .await?;
+    let input = program.decode_input(&req.input)?;
+    state.pre_execute(&input).await?;
+    let input_hash = input.digest();
+
+    let job = state.jobs.submit(
+        JobKind::Benchmark,
+        req.program_id.clone(),
+        Some(program.version.clone()),
+        client_id.clone(),
+        input.block_number(),
+        Some(input_hash.clone()),
+    );
+    let job_id = job.job_id();
+    let permit = state
+        .scheduler
+        .acquire(Ticket {
+            job_id,
+            client_id,
+            program_id: req.program_id.clone(),
+            priority: req.priority,
+        })
+        .await;
+    job.start();
+    let started_at = now_millis();
+
+    let (mode, warmup, runs) = (req.mode, req.warmup, req.runs);
+    let vm = program.instance.vm;
+    let guest_input = input.to_input();
+    let (lifecycle, jobs) = (state.lifecycle.clone(), state.jobs.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let hardware = hardware_fingerprint();
+        let mut samples = Samples::default();
+        for run in 0..warmup + runs {
+            // A benchmark can hold the slot for a long time, so it gives it up between runs
+            // once the server stops or the job was cancelled
+            let cancelled = jobs
+                .get(job_id)
+                .is_some_and(|job| job.status == JobStatus::Cancelled);
+            if cancelled || lifecycle.mode() == Mode::ShuttingDown {
+                return Err(Stopped::Interrupted(format!(
+                    "Benchmark interrupted after {run} of {} runs",
+                    warmup + runs
+                )));
+            }
+            let measured = run >= warmup;
+            if mode.executes() {
+                let start = Instant::now();
+                let report = vm
+                    .execute(&guest_input)
+                    .map_err(|e| Stopped::Failed(format!("Failed to execute program: {}", e)))?;
+                if measured {
+                    samples
+                        .execute_wall_time
+                        .push(milliseconds(start.elapsed()));
+                    samples.cycles.push(report.total_num_cycles as f64);
+                }
+            }
+            if mode.proves() {
+                let sampler = MemorySampler::start();
+                let start = Instant::now();
+                let proved = vm.prove(&guest_input);
+                let wall_time = start.elapsed();
+                let peak_memory = sampler.stop();
+                let (proof, report) = proved
+                    .map_err(|e| Stopped::Failed(format!("Failed to generate proof: {}", e)))?;
+                if measured {
+                    samples.prove_wall_time.push(milliseconds(wall_time));
+                    samples.proving_time.push(milliseconds(report.proving_time));
+                    samples.proof_size.push(proof.len() as f64);
+                    samples
+                        .peak_memory
+                        .extend(peak_memory.map(|bytes| bytes as f64));
+                }
+            }
+        }
+        Ok((hardware, samples))
+    })
+    .await
+    .map_err(|e| Stopped::Failed(format!("Benchmark task failed: {}", e)))
+    .and_then(|result| result);
+    let (hardware, samples) = match result {
+        Ok(result) => result,
+        Err(Stopped::Failed(message)) => {
+            job.failed(message.clone());
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, message));
+        }
+        Err(Stopped::Interrupted(message)) => {
+            job.cancelled(message.clone());
+            return Err((StatusCode::SERVICE_UNAVAILABLE, message));
+        }
+    };
+
+    let execute = mode.executes().then(|| ExecuteStatistics {
+        wall_time_milliseconds: Statistics::new(samples.execute_wall_time),
+        cycles: Statistics::new(samples.cycles),
+    });
+    let prove = mode.proves().then(|| ProveStatistics {
+        wall_time_milliseconds: Statistics::new(samples.prove_wall_time),
+        proving_time_milliseconds: Statistics::new(samples.proving_time),
+        proof_size: Statistics::new(samples.proof_size),
+        // Only complete when every run was sampled
+        peak_memory_bytes: (samples.peak_memory.len() == runs)
+            .then(|| Statistics::new(samples.peak_memory)),
+    });
+    job.benchmarked(
+        execute.as_ref().map(|stats| stats.cycles.median as u64),
+        prove
+            .as_ref()
+            .map(|stats| stats.proving_time_milliseconds.median as u128),
+    );
+
+    let report = BenchmarkReport {
+        schema_version: SCHEMA_VERSION,
+        benchmark_id: job_id,
+        program_id: req.program_id,
+        version: program.version,
+        input_hash,
+        mode,
+        warmup,
+        runs,
+        started_at,
+        finished_at: now_millis(),
+        hardware,
+        execute,
+        prove,
+    };
+    state.benchmarks.record(report.clone());
+    Ok(Json(report))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListParams {
+    pub limit: Option<usize>,
+}
+
+/// Recorded benchmarks, newest first
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn list_benchmarks(
+    State(state): State<AppState>,
+    Query(filter): Query<BenchmarkFilter>,
+    Query(params): Query<ListParams>,
+) -> Json<Vec<BenchmarkReport>> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    Json(state.benchmarks.list(&filter, limit))
+}
+
+/// Overrides of the configured regression policy
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RegressionParams {
+    pub threshold_percent: Option<f64>,
+    pub baseline_runs: Option<usize>,
+}
+
+/// Check the latest benchmark of every series against the benchmarks before it
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_regressions(
+    State(state): State<AppState>,
+    Query(filter): Query<BenchmarkFilter>,
+    Query(params): Query<RegressionParams>,
+) -> Result<Json<RegressionReport>, (StatusCode, String)> {
+    let mut policy = state.benchmarks.policy();
+    if let Some(threshold_percent) = params.threshold_percent {
+        if threshold_percent.is_nan() || threshold_percent < 0.0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "threshold_percent must not be negative".to_string(),
+            ));
+        }
+        policy.threshold_percent = threshold_percent;
+    }
+    if let Some(baseline_runs) = params.baseline_runs {
+        if baseline_runs == 0 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "baseline_runs must be at least 1".to_string(),
+            ));
+        }
+        policy.baseline_runs = baseline_runs;
+    }
+    Ok(Json(state.benchmarks.regressions(&filter, policy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{zkVMInstance, zkVMVendor};
+    use crate::config::{Config, MockConfig};
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::test_input;
+    use std::sync::Arc;
+
+    async fn state_with_program(mock: MockConfig) -> AppState {
+        let state = AppState::new(&Config::default());
+        state.programs.write().await.insert(
+            ProgramID("sp1".to_string()),
+            zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::new(mock))),
+        );
+        state
+    }
+
+    fn request(mode: BenchmarkMode, runs: usize) -> Json<BenchmarkRequest> {
+        Json(BenchmarkRequest {
+            program_id: ProgramID("sp1".to_string()),
+            version: None,
+            input: test_input(),
+            mode,
+            runs,
+            warmup: 1,
+            priority: Priority::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_execute() {
+        let state = state_with_program(MockConfig::default()).await;
+
+        let report = run_benchmark(
+            State(state.clone()),
+            ClientID::default(),
+            request(BenchmarkMode::Execute, 3),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(report.schema_version, SCHEMA_VERSION);
+        assert_eq!(report.hardware, hardware_fingerprint());
+        assert!(report.prove.is_none());
+
+        let execute = report.execute.unwrap();
+        assert_eq!(execute.cycles.samples.len(), 3);
+        // The mock reports the same cycles for the same input
+        assert_eq!(execute.cycles.stddev, 0.0);
+        assert!(execute.wall_time_milliseconds.min >= 1.0);
+
+        let job = state.jobs.get(report.benchmark_id).unwrap();
+        assert_eq!(job.kind, JobKind::Benchmark);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.total_num_cycles, Some(execute.cycles.median as u64));
+        // Every slot was given back
+        assert_eq!(state.scheduler.running(), 0);
+
+        let listed = list_benchmarks(
+            State(state),
+            Query(BenchmarkFilter::default()),
+            Query(ListParams::default()),
+        )
+        .await
+        .0;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].benchmark_id, report.benchmark_id);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_limits() {
+        let state = state_with_program(MockConfig::default()).await;
+        let limits = state.benchmark_limits;
+
+        for (runs, warmup) in [(0, 1), (limits.max_runs + 1, 1), (1, limits.max_warmup + 1)] {
+            let mut req = request(BenchmarkMode::Execute, runs);
+            req.warmup = warmup;
+            let (status, _) = run_benchmark(State(state.clone()), ClientID::default(), req)
+                .await
+                .unwrap_err();
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        assert!(
+            state
+                .jobs
+                .list(&Default::default(), None, 10)
+                .jobs
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_interrupted_by_shutdown() {
+        let state = state_with_program(MockConfig {
+            execute_latency: Duration::from_millis(50),
+            ..MockConfig::default()
+        })
+        .await;
+
+        let benchmark = tokio::spawn(run_benchmark(
+            State(state.clone()),
+            ClientID::default(),
+            request(BenchmarkMode::Execute, 100),
+        ));
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        state.lifecycle.begin_shutdown();
+
+        let (status, message) = benchmark.await.unwrap().unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(message.starts_with("Benchmark interrupted after"));
+        let job = &state.jobs.list(&Default::default(), None, 10).jobs[0];
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert_eq!(state.scheduler.running(), 0);
+        assert!(
+            state
+                .benchmarks
+                .list(&BenchmarkFilter::default(), 10)
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_regressions_after_bump() {
+        let state = AppState::new(&Config::default());
+        let program_id = ProgramID("sp1".to_string());
+        {
+            let mut programs = state.programs.write().await;
+            for (version, cycles_per_byte) in [("v1", 1), ("v2", 2)] {
+                let vm = MockZkVM::new(MockConfig {
+                    cycles_per_byte,
+                    ..Default::default()
+                });
+                programs.add_version(
+                    version.to_string(),
+                    zkVMInstance::new(zkVMVendor::SP1, Arc::new(vm)),
+                );
+            }
+        }
+
+        for version in ["v1", "v1", "v2"] {
+            state
+                .programs
+                .write()
+                .await
+                .set_alias(program_id.clone(), version);
+            let report = run_benchmark(
+                State(state.clone()),
+                ClientID::default(),
+                request(BenchmarkMode::Execute, 1),
+            )
+            .await
+            .unwrap();
+            assert_eq!(report.version, version);
+        }
+
+        let report = get_regressions(
+            State(state.clone()),
+            Query(BenchmarkFilter::default()),
+            Query(RegressionParams::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert!(report.regressed);
+        assert_eq!(report.threshold_percent, 5.0);
+        let series = &report.series[0];
+        assert_eq!(series.version, "v2");
+        assert_eq!(series.metrics[0].baseline_versions, ["v1"]);
+
+        let (status, _) = get_regressions(
+            State(state),
+            Query(BenchmarkFilter::default()),
+            Query(RegressionParams {
+                threshold_percent: None,
+                baseline_runs: Some(0),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_both() {
+        let state = state_with_program(MockConfig::default()).await;
+
+        let report = run_benchmark(
+            State(state),
+            ClientID::default(),
+            request(BenchmarkMode::Both, 2),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(report.execute.unwrap().cycles.samples.len(), 2);
+        let prove = report.prove.unwrap();
+        assert_eq!(prove.proving_time_milliseconds.samples.len(), 2);
+        assert!(prove.proof_size.min > 0.0);
+        assert!(prove.peak_memory_bytes.unwrap().min > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_errors() {
+        let state = state_with_program(MockConfig::default()).await;
+        let (status, _) = run_benchmark(
+            State(state),
+            ClientID::default(),
+            request(BenchmarkMode::Execute, 0),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let state = state_with_program(MockConfig {
+            failure_rate: 1.0,
+            ..Default::default()
+        })
+        .await;
+        let (status, message) = run_benchmark(
+            State(state.clone()),
+            ClientID::default(),
+            request(BenchmarkMode::Prove, 1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(message.starts_with("Failed to generate proof"));
+        assert_eq!(state.scheduler.running(), 0);
+    }
+}
@@ -0,0 +1,225 @@
+//! Comparing the cycles of two executions, region by region.
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::execute::{ExecuteRequest, ExecuteResponse, execute};
+use crate::common::{AppState, ClientID, ProgramID};
+use crate::profile::{RegionComparison, compare_regions};
+
+/// One side of a comparison
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CompareSide {
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Replaces the shared `input` for this side
+    #[serde(default)]
+    pub input: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareRequest {
+    pub program_id: ProgramID,
+    /// Input of both runs, unless a side has its own
+    #[serde(default)]
+    pub input: Option<serde_json::Value>,
+    #[serde(default)]
+    pub baseline: CompareSide,
+    #[serde(default)]
+    pub candidate: CompareSide,
+    /// Run both guests in profile mode
+    #[serde(default)]
+    pub profile: bool,
+}
+
+/// Summary of one of the compared executions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareRun {
+    pub job_id: Uuid,
+    pub version: String,
+    pub total_num_cycles: u64,
+    pub execution_time_duration: Duration,
+    /// Whether the blocks are valid, when they were validated natively
+    pub valid: Option<bool>,
+}
+
+impl From<&ExecuteResponse> for CompareRun {
+    fn from(response: &ExecuteResponse) -> Self {
+        CompareRun {
+            job_id: response.job_id,
+            version: response.version.clone(),
+            total_num_cycles: response.total_num_cycles,
+            execution_time_duration: response.execution_time_duration,
+            valid: response.native.as_ref().map(|native| native.outcome.valid),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompareResponse {
+    pub program_id: ProgramID,
+    pub baseline: CompareRun,
+    pub candidate: CompareRun,
+    #[serde(flatten)]
+    pub comparison: RegionComparison,
+}
+
+/// Execute a baseline and a candidate run, one after the other, and compare their regions
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn compare_executions(
+    State(state): State<AppState>,
+    client_id: ClientID,
+    Json(req): Json<CompareRequest>,
+) -> Result<Json<CompareResponse>, (StatusCode, String)> {
+    let mut runs = Vec::with_capacity(2);
+    for (name, side) in [("baseline", req.baseline), ("candidate", req.candidate)] {
+        let input = side.input.or_else(|| req.input.clone()).ok_or_else(|| {
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("{name} has no input: set input or {name}.input"),
+            )
+        })?;
+        runs.push(ExecuteRequest {
+            program_id: req.program_id.clone(),
+            version: side.version,
+            input,
+            profile: req.profile,
+            export: None,
+        });
+    }
+
+    let mut responses = Vec::with_capacity(2);
+    for run in runs {
+        responses.push(execute(&state, client_id.clone(), run).await?);
+    }
+    let (baseline, candidate) = (&responses[0], &responses[1]);
+
+    Ok(Json(CompareResponse {
+        program_id: req.program_id,
+        comparison: compare_regions(
+            baseline.total_num_cycles,
+            &baseline.region_cycles,
+            candidate.total_num_cycles,
+            &candidate.region_cycles,
+        ),
+        baseline: baseline.into(),
+        candidate: candidate.into(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{zkVMInstance, zkVMVendor};
+    use crate::config::{Config, MockConfig};
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::{test_block_range, test_input};
+    use std::sync::Arc;
+
+    async fn state_with_versions() -> AppState {
+        let state = AppState::new(&Config::default());
+        let mut programs = state.programs.write().await;
+        for (version, cycles_per_byte) in [("v1", 2), ("v2", 1)] {
+            let vm = MockZkVM::new(MockConfig {
+                cycles_per_byte,
+                ..Default::default()
+            });
+            programs.add_version(
+                version.to_string(),
+                zkVMInstance::new(zkVMVendor::SP1, Arc::new(vm)),
+            );
+        }
+        // The alias pointed at v2 before, so v2 can be pinned
+        programs.set_alias(ProgramID("sp1".to_string()), "v2");
+        programs.set_alias(ProgramID("sp1".to_string()), "v1");
+        drop(programs);
+        state
+    }
+
+    #[tokio::test]
+    async fn test_compare_versions() {
+        let state = state_with_versions().await;
+        let request = CompareRequest {
+            program_id: ProgramID("sp1".to_string()),
+            input: Some(test_input()),
+            baseline: CompareSide::default(),
+            candidate: CompareSide {
+                version: Some("v2".to_string()),
+                input: None,
+            },
+            profile: false,
+        };
+
+        let response = compare_executions(State(state), ClientID::default(), Json(request))
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(response.baseline.version, "v1");
+        assert_eq!(response.candidate.version, "v2");
+        let total = &response.comparison.total;
+        assert_eq!(
+            total.baseline_cycles,
+            Some(response.baseline.total_num_cycles)
+        );
+        assert!(total.delta_cycles < 0);
+        assert!(total.change_percent.unwrap() < 0.0);
+        // Native validation is off by default
+        assert_eq!(response.baseline.valid, None);
+    }
+
+    #[tokio::test]
+    async fn test_compare_inputs() {
+        let state = state_with_versions().await;
+        let request = CompareRequest {
+            program_id: ProgramID("sp1".to_string()),
+            input: None,
+            baseline: CompareSide {
+                version: None,
+                input: Some(test_input()),
+            },
+            candidate: CompareSide {
+                version: None,
+                input: Some(test_block_range()),
+            },
+            profile: false,
+        };
+
+        let response = compare_executions(State(state), ClientID::default(), Json(request))
+            .await
+            .unwrap()
+            .0;
+        // Two blocks are more input than one
+        assert!(response.comparison.total.delta_cycles > 0);
+        assert_ne!(response.baseline.job_id, response.candidate.job_id);
+    }
+
+    #[tokio::test]
+    async fn test_compare_without_input() {
+        let state = state_with_versions().await;
+        let request = CompareRequest {
+            program_id: ProgramID("sp1".to_string()),
+            input: None,
+            baseline: CompareSide {
+                version: None,
+                input: Some(test_input()),
+            },
+            candidate: CompareSide::default(),
+            profile: false,
+        };
+
+        let (status, message) =
+            compare_executions(State(state), ClientID::default(), Json(request))
+                .await
+                .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            message,
+            "candidate has no input: set input or candidate.input"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Estimating what proving an input would take on this host, from its cycles.
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::execute::{ExecuteRequest, execute};
+use super::info::hardware_fingerprint;
+use crate::benchmark::{Prediction, predict};
+use crate::common::{AppState, ClientID, ProgramID};
+use crate::program::NativeValidation;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EstimateRequest {
+    pub program_id: ProgramID,
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Decoded according to the input kind of the program
+    pub input: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EstimateResponse {
+    /// The execute job the cycles come from
+    pub job_id: Uuid,
+    pub program_id: ProgramID,
+    pub version: String,
+    pub total_num_cycles: u64,
+    pub execution_time_duration: Duration,
+    /// Outcome and statistics of the native validation of the blocks, when enabled
+    pub native: Option<NativeValidation>,
+    /// Digest of the hardware fingerprint the calibration is taken from
+    pub hardware: String,
+    /// Which benchmarks the predictions are calibrated from, `None` without any
+    pub calibration: Option<Calibration>,
+    /// `None` until the program was benchmarked in `both` mode on this host
+    pub proving_time_milliseconds: Option<Prediction>,
+    /// `None` until the program was benchmarked in `both` mode on a platform reporting memory
+    pub peak_memory_bytes: Option<Prediction>,
+}
+
+/// The benchmarks a prediction is calibrated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Calibration {
+    /// Benchmarks of the version that executed
+    Version,
+    /// Benchmarks of other versions of the program, since the one that executed has none
+    OtherVersions,
+}
+
+/// Execute an input, and predict its proving time and memory from the benchmarks of the
+/// program on this host
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn estimate_proof(
+    State(state): State<AppState>,
+    client_id: ClientID,
+    Json(req): Json<EstimateRequest>,
+) -> Result<Json<EstimateResponse>, (StatusCode, String)> {
+    let executed = execute(
+        &state,
+        client_id,
+        ExecuteRequest {
+            program_id: req.program_id,
+            version: req.version,
+            input: req.input,
+            profile: false,
+            export: None,
+        },
+    )
+    .await?;
+
+    let hardware = hardware_fingerprint().digest;
+    // Other versions of the program are a fallback, since a guest change can change the cost
+    // of a cycle
+    let mut calibration = Calibration::Version;
+    let mut observations =
+        state
+            .benchmarks
+            .observations(&executed.program_id, Some(&executed.version), &hardware);
+    if observations.is_empty() {
+        calibration = Calibration::OtherVersions;
+        observations = state
+            .benchmarks
+            .observations(&executed.program_id, None, &hardware);
+    }
+    let cycles = executed.total_num_cycles as f64;
+    let proving_times: Vec<(f64, f64)> = observations
+        .iter()
+        .map(|observation| (observation.cycles, observation.proving_time_milliseconds))
+        .collect();
+    let peak_memory: Vec<(f64, f64)> = observations
+        .iter()
+        .filter_map(|observation| Some((observation.cycles, observation.peak_memory_bytes?)))
+        .collect();
+
+    Ok(Json(EstimateResponse {
+        job_id: executed.job_id,
+        program_id: executed.program_id,
+        version: executed.version,
+        total_num_cycles: executed.total_num_cycles,
+        execution_time_duration: executed.execution_time_duration,
+        native: executed.native,
+        hardware,
+        calibration: (!observations.is_empty()).then_some(calibration),
+        proving_time_milliseconds: predict(&proving_times, cycles),
+        peak_memory_bytes: predict(&peak_memory, cycles),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::BenchmarkMode;
+    use crate::common::{zkVMInstance, zkVMVendor};
+    use crate::config::Config;
+    use crate::endpoints::benchmark::{BenchmarkRequest, run_benchmark};
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::{test_block_range, test_input};
+    use crate::scheduler::Priority;
+    use std::sync::Arc;
+
+    fn request(input: serde_json::Value) -> Json<EstimateRequest> {
+        Json(EstimateRequest {
+            program_id: ProgramID("sp1".to_string()),
+            version: None,
+            input,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_estimate() {
+        let state = AppState::new(&Config::default());
+        state.programs.write().await.insert(
+            ProgramID("sp1".to_string()),
+            zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+        );
+
+        // Without calibration only the cycles are known
+        let response = estimate_proof(
+            State(state.clone()),
+            ClientID::default(),
+            request(test_input()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert!(response.total_num_cycles > 0);
+        assert_eq!(response.hardware, hardware_fingerprint().digest);
+        assert_eq!(response.proving_time_milliseconds, None);
+        assert_eq!(response.calibration, None);
+
+        let benchmark = BenchmarkRequest {
+            program_id: ProgramID("sp1".to_string()),
+            version: None,
+            input: test_input(),
+            mode: BenchmarkMode::Both,
+            runs: 1,
+            warmup: 0,
+            priority: Priority::default(),
+        };
+        let report = run_benchmark(State(state.clone()), ClientID::default(), Json(benchmark))
+            .await
+            .unwrap();
+        assert!(report.prove.is_some());
+
+        let response = estimate_proof(
+            State(state.clone()),
+            ClientID::default(),
+            request(test_block_range()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(response.calibration, Some(Calibration::Version));
+        let proving_time = response.proving_time_milliseconds.unwrap();
+        assert_eq!(proving_time.observations, 1);
+        assert!(proving_time.low <= proving_time.estimate);
+        assert!(proving_time.estimate <= proving_time.high);
+        assert!(response.peak_memory_bytes.unwrap().estimate > 0.0);
+
+        // A version without benchmarks of its own borrows those of the others
+        {
+            let mut programs = state.programs.write().await;
+            programs.add_version(
+                "v2".to_string(),
+                zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+            );
+            // Pointed at once, so that it can be pinned
+            programs.set_alias(ProgramID("sp1".to_string()), "v2");
+            programs.set_alias(ProgramID("sp1".to_string()), "sp1");
+        }
+        let mut pinned = request(test_input());
+        pinned.version = Some("v2".to_string());
+        let response = estimate_proof(State(state), ClientID::default(), pinned)
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(response.version, "v2");
+        assert_eq!(response.calibration, Some(Calibration::OtherVersions));
+        assert_eq!(response.proving_time_milliseconds.unwrap().observations, 1);
+    }
+}
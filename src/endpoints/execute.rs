@@ -3,69 +3,150 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 use tracing::instrument;
-use zkvm_interface::{Input, zkVM};
+use uuid::Uuid;
+use zkvm_interface::zkVM;
 
-use crate::common::{AppState, ProgramID};
-use crate::program::ProgramInput;
+use crate::common::{AppState, ClientID, ProgramID};
+use crate::jobs::JobKind;
+use crate::profile::{ProfileFormat, RegionNode, region_tree};
+use crate::program::{CycleAttribution, NativeValidation, attribute_cycles};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecuteRequest {
     pub program_id: ProgramID,
-    pub input: ProgramInput,
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Decoded according to the input kind of the program
+    pub input: serde_json::Value,
+    /// Run the guest in profile mode, to attribute cycles to blocks and transactions
+    #[serde(default)]
+    pub profile: bool,
+    /// Also export the region tree in this format
+    #[serde(default)]
+    pub export: Option<ProfileFormat>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecuteResponse {
+    pub job_id: Uuid,
     pub program_id: ProgramID,
+    /// Version the program ran on
+    pub version: String,
     pub total_num_cycles: u64,
     pub region_cycles: IndexMap<String, u64>,
+    /// The regions nested by name, with self and total cycles
+    pub region_tree: RegionNode,
     pub execution_time_duration: Duration,
+    /// Outcome and statistics of the native validation of the blocks, when enabled. An
+    /// execution reports no public values, so the outcome the guest commits is only known
+    /// from a proof.
+    pub native: Option<NativeValidation>,
+    /// Cycles by block, transaction and precompile, for a profiled run
+    pub attribution: Option<CycleAttribution>,
+    /// The region tree in the requested format; collapsed stacks are a string
+    pub export: Option<serde_json::Value>,
 }
 
 #[axum::debug_handler]
 #[instrument(skip_all)]
 pub async fn execute_program(
     State(state): State<AppState>,
+    client_id: ClientID,
     Json(req): Json<ExecuteRequest>,
 ) -> Result<Json<ExecuteResponse>, (StatusCode, String)> {
+    execute(&state, client_id, req).await.map(Json)
+}
+
+/// Run one execution as a job, shared by `/execute` and `/execute/compare`
+pub(crate) async fn execute(
+    state: &AppState,
+    client_id: ClientID,
+    req: ExecuteRequest,
+) -> Result<ExecuteResponse, (StatusCode, String)> {
+    state.lifecycle.admit().await?;
+
     let program_id = req.program_id.clone();
-    let programs = state.programs.read().await;
+    let program = state.resolve(&program_id, req.version.as_deref()).await?;
+    let input = program.decode_input(&req.input)?;
+    if req.profile && input.blocks().is_none() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Programs taking {} input have no profile mode",
+                program.instance.input
+            ),
+        ));
+    }
+    let native = state.pre_execute(&input).await?;
 
-    let program = programs
-        .get(&program_id)
-        .ok_or((StatusCode::NOT_FOUND, "Program not found".to_string()))?;
+    let job = state.jobs.submit(
+        JobKind::Execute,
+        program_id.clone(),
+        Some(program.version.clone()),
+        client_id,
+        input.block_number(),
+        Some(input.digest()),
+    );
+    let guest_input = if req.profile {
+        input.to_profiled_input()
+    } else {
+        input.to_input()
+    };
 
-    let input: Input = req.input.into();
+    // Executions run for seconds, so they stay off the async workers
+    let vm = program.instance.vm.clone();
+    let executed = tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        vm.execute(&guest_input)
+            .map(|report| (report, start.elapsed()))
+            .map_err(|e| format!("Failed to execute program: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Execution task failed: {}", e))
+    .and_then(|result| result);
+    let (report, execution_time_duration) = match executed {
+        Ok(executed) => executed,
+        Err(message) => {
+            job.failed(message.clone());
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, message));
+        }
+    };
 
-    let start = Instant::now();
-    let report = program.vm.execute(&input).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to execute program: {}", e),
-        )
-    })?;
-    let execution_time_duration = start.elapsed();
+    let job_id = job.job_id();
+    job.executed(report.total_num_cycles);
+    let attribution = input
+        .blocks()
+        .and_then(|(blocks, _)| attribute_cycles(&report.region_cycles, blocks));
+    let region_tree = region_tree(report.total_num_cycles, &report.region_cycles);
+    let export = req.export.map(|format| region_tree.export(format));
 
-    Ok(Json(ExecuteResponse {
+    Ok(ExecuteResponse {
+        job_id,
         program_id,
+        version: program.version,
         total_num_cycles: report.total_num_cycles,
         region_cycles: report.region_cycles,
+        region_tree,
         execution_time_duration,
-    }))
+        native,
+        attribution,
+        export,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::common::{ProgramID, zkVMInstance};
-    use crate::mock_zkvm::MockZkVM;
+    use crate::config::Config;
+    use crate::mock_zkvm::{MockZkVM, mock_block_validation};
+    use crate::program::{InputKind, test_input};
 
-    use std::collections::HashMap;
     use std::fs;
 
     use std::sync::Arc;
     use tempfile::TempDir;
-    use tokio::sync::RwLock;
 
     // Helper function to create a test AppState
     fn create_test_state() -> (AppState, TempDir) {
@@ -73,9 +154,7 @@ mod tests {
         let programs_dir = temp_dir.path().join("programs");
         fs::create_dir_all(&programs_dir).unwrap();
 
-        let state = AppState {
-            programs: Arc::new(RwLock::new(HashMap::new())),
-        };
+        let state = AppState::new(&Config::default());
 
         (state, temp_dir)
     }
@@ -97,16 +176,30 @@ mod tests {
 
         let request = ExecuteRequest {
             program_id: program_id.clone(),
-            input: ProgramInput::test_input(),
+            version: None,
+            input: test_input(),
+            profile: false,
+            export: Some(ProfileFormat::Collapsed),
         };
 
-        let result = execute_program(State(state), Json(request)).await;
+        let state_jobs = state.jobs.clone();
+        let result = execute_program(State(state), ClientID::default(), Json(request)).await;
 
         assert!(result.is_ok());
         let response = result.unwrap().0;
         assert_eq!(response.program_id, program_id);
         assert!(response.total_num_cycles > 0);
         assert!(response.execution_time_duration.as_millis() > 0);
+        // The mock reports no regions, so the whole run is the root
+        assert_eq!(response.region_tree.self_cycles, response.total_num_cycles);
+        assert_eq!(
+            response.export.unwrap(),
+            format!("total {}", response.total_num_cycles)
+        );
+
+        let job = state_jobs.get(response.job_id).unwrap();
+        assert_eq!(job.status, crate::jobs::JobStatus::Succeeded);
+        assert_eq!(job.total_num_cycles, Some(response.total_num_cycles));
     }
 
     #[tokio::test]
@@ -115,14 +208,191 @@ mod tests {
 
         let request = ExecuteRequest {
             program_id: ProgramID("non_existent".to_string()),
-            input: ProgramInput::test_input(),
+            version: None,
+            input: test_input(),
+            profile: false,
+            export: None,
         };
 
-        let result = execute_program(State(state), Json(request)).await;
+        let result = execute_program(State(state), ClientID::default(), Json(request)).await;
 
         assert!(result.is_err());
         let (status, message) = result.unwrap_err();
         assert_eq!(status, StatusCode::NOT_FOUND);
         assert_eq!(message, "Program not found");
     }
+
+    #[tokio::test]
+    async fn test_execute_raw_input() {
+        let (state, _temp_dir) = create_test_state();
+        let program_id = ProgramID("raw".to_string());
+        state.programs.write().await.insert(
+            program_id.clone(),
+            zkVMInstance::new(
+                crate::common::zkVMVendor::Mock,
+                Arc::new(MockZkVM::default()),
+            )
+            .with_input(InputKind::Raw),
+        );
+
+        // "hello" in base64
+        let request = ExecuteRequest {
+            program_id: program_id.clone(),
+            version: None,
+            input: serde_json::json!("aGVsbG8="),
+            profile: false,
+            export: None,
+        };
+        let response = execute_program(State(state.clone()), ClientID::default(), Json(request))
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(response.total_num_cycles, 105);
+
+        // A ClientInput is not valid for a raw program
+        let request = ExecuteRequest {
+            program_id: program_id.clone(),
+            version: None,
+            input: test_input(),
+            profile: false,
+            export: None,
+        };
+        let (status, message) =
+            execute_program(State(state.clone()), ClientID::default(), Json(request))
+                .await
+                .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(message.starts_with("Invalid raw input"));
+
+        // Only programs taking `ClientInput`s have a profile mode
+        let request = ExecuteRequest {
+            program_id,
+            version: None,
+            input: serde_json::json!("aGVsbG8="),
+            profile: true,
+            export: None,
+        };
+        let (status, _) = execute_program(State(state), ClientID::default(), Json(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    fn invalid_block_input() -> serde_json::Value {
+        // A block using more gas than its limit
+        let mut input = test_input();
+        let mut header: alloy_consensus::Header =
+            serde_json::from_value(input["block"]["header"].take()).unwrap();
+        header.gas_used = header.gas_limit + 1;
+        input["block"]["header"] = serde_json::to_value(header).unwrap();
+        input
+    }
+
+    async fn state_with_program(config: &Config) -> AppState {
+        let state = AppState::new(config).with_block_validator(mock_block_validation);
+        state.programs.write().await.insert(
+            ProgramID("sp1".to_string()),
+            zkVMInstance::new(
+                crate::common::zkVMVendor::SP1,
+                Arc::new(MockZkVM::default()),
+            ),
+        );
+        state
+    }
+
+    fn request(input: serde_json::Value) -> Json<ExecuteRequest> {
+        Json(ExecuteRequest {
+            program_id: ProgramID("sp1".to_string()),
+            version: None,
+            input,
+            profile: false,
+            export: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_validation_outcome() {
+        let state = state_with_program(&Config {
+            native_validation: true,
+            ..Config::default()
+        })
+        .await;
+
+        let response = execute_program(
+            State(state.clone()),
+            ClientID::default(),
+            request(test_input()),
+        )
+        .await
+        .unwrap()
+        .0;
+        let native = response.native.unwrap();
+        assert!(native.outcome.valid);
+        assert!(native.outcome.block_hash.is_some());
+        assert_eq!(native.report.transaction_count, 0);
+
+        // An invalid block is still executed, and reported as such
+        let response = execute_program(
+            State(state),
+            ClientID::default(),
+            request(invalid_block_input()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert!(response.total_num_cycles > 0);
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["native"]["outcome"]["valid"], false);
+        assert_eq!(
+            json["native"]["outcome"]["reason"]["code"],
+            "stateless_execution_failed"
+        );
+
+        // Native validation is opt-in
+        let state = state_with_program(&Config::default()).await;
+        let response = execute_program(State(state), ClientID::default(), request(test_input()))
+            .await
+            .unwrap()
+            .0;
+        assert!(response.native.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reject_invalid_blocks() {
+        let state = state_with_program(&Config {
+            reject_invalid_blocks: true,
+            ..Config::default()
+        })
+        .await;
+
+        let (status, message) = execute_program(
+            State(state),
+            ClientID::default(),
+            request(invalid_block_input()),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(message.starts_with("Block 10 failed stateless validation"));
+    }
+
+    #[tokio::test]
+    async fn test_backend_failure() {
+        let state = AppState::new(&Config::default());
+        state.programs.write().await.insert(
+            ProgramID("sp1".to_string()),
+            zkVMInstance::new(
+                crate::common::zkVMVendor::SP1,
+                Arc::new(MockZkVM::new(crate::config::MockConfig {
+                    failure_rate: 1.0,
+                    ..Default::default()
+                })),
+            ),
+        );
+
+        let (status, _) = execute_program(State(state), ClientID::default(), request(test_input()))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
 }
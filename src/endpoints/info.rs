@@ -1,7 +1,9 @@
 //! Collects and caches host hardware / OS data for the "/info" route.
 
 use axum::Json;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
 use sysinfo::System;
 use tracing::instrument;
 use wgpu::{Backends, Instance, InstanceDescriptor};
@@ -13,6 +15,7 @@ pub struct ServerInfoResponse {
     pub os: OsInfo,
     pub architecture: String,
     pub gpu: String,
+    pub fingerprint: HardwareFingerprint,
 }
 
 #[derive(Debug, Serialize)]
@@ -37,6 +40,50 @@ pub struct OsInfo {
     pub kernel: String,
 }
 
+/// The parts of the host that do not change between runs, to tell apart measurements taken on
+/// different machines
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HardwareFingerprint {
+    pub cpu_model: String,
+    pub cpu_cores: usize,
+    pub memory_total_bytes: u64,
+    pub architecture: String,
+    pub gpu: String,
+    /// Hex encoded SHA-256 of the fields above
+    pub digest: String,
+}
+
+/// Get the fingerprint of the host, collected on the first call
+pub fn hardware_fingerprint() -> HardwareFingerprint {
+    static FINGERPRINT: OnceLock<HardwareFingerprint> = OnceLock::new();
+    FINGERPRINT
+        .get_or_init(|| {
+            let cpu = get_cpu_info();
+            let mut sys = System::new();
+            sys.refresh_memory();
+            let memory_total_bytes = sys.total_memory();
+            let architecture = std::env::consts::ARCH.to_string();
+            let gpu = get_gpu_info();
+
+            let digest = Sha256::new()
+                .chain_update(&cpu.model)
+                .chain_update(cpu.cores.to_le_bytes())
+                .chain_update(memory_total_bytes.to_le_bytes())
+                .chain_update(&architecture)
+                .chain_update(&gpu)
+                .finalize();
+            HardwareFingerprint {
+                cpu_model: cpu.model,
+                cpu_cores: cpu.cores,
+                memory_total_bytes,
+                architecture,
+                gpu,
+                digest: hex::encode(digest),
+            }
+        })
+        .clone()
+}
+
 /// Get informatio about the CPU
 /// Note: unfortunately this has been unreliable on ARM macs and AWS machines
 fn get_cpu_info() -> CpuInfo {
@@ -106,6 +153,7 @@ pub async fn get_server_info() -> Json<ServerInfoResponse> {
         os: get_os_info(),
         architecture: std::env::consts::ARCH.into(),
         gpu: get_gpu_info(),
+        fingerprint: hardware_fingerprint(),
     })
 }
 #[cfg(test)]
@@ -121,5 +169,7 @@ mod tests {
         assert!(!info.0.os.name.is_empty());
         assert!(!info.0.architecture.is_empty());
         assert!(!info.0.gpu.is_empty());
+        assert_eq!(info.0.fingerprint.digest.len(), 64);
+        assert_eq!(info.0.fingerprint, super::hardware_fingerprint());
     }
 }
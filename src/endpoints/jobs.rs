@@ -0,0 +1,149 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::common::AppState;
+use crate::endpoints::prove::ProveResponse;
+use crate::jobs::{JobFilter, JobPage, JobSummary};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PageParams {
+    /// `next_cursor` from the previous page
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    Query(filter): Query<JobFilter>,
+    Query(page): Query<PageParams>,
+) -> Json<JobPage> {
+    let limit = page
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    Json(state.jobs.list(&filter, page.cursor, limit))
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<JobSummary>, (StatusCode, String)> {
+    state
+        .jobs
+        .get(job_id)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "Job not found".to_string()))
+}
+
+/// Proof produced by a finished prove job, including jobs from before a restart
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_job_proof(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<ProveResponse>, (StatusCode, String)> {
+    let job = state
+        .jobs
+        .get(job_id)
+        .ok_or((StatusCode::NOT_FOUND, "Job not found".to_string()))?;
+
+    let proof = state
+        .jobs
+        .proof(job_id)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read proof: {}", e),
+            )
+        })?
+        .ok_or((StatusCode::NOT_FOUND, "Proof not available".to_string()))?;
+    // The version may have been unloaded since, then the outcome is left out
+    let version = job.version.unwrap_or_default();
+    let outcome = match state.resolve(&job.program_id, Some(&version)).await {
+        Ok(program) => program
+            .committed_outcome(&proof)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        Err(_) => None,
+    };
+
+    Ok(Json(ProveResponse {
+        job_id,
+        program_id: job.program_id,
+        version,
+        proof,
+        proving_time_milliseconds: job.proving_time_milliseconds.unwrap_or_default(),
+        outcome,
+        native: None,
+        native_mismatch: false,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ClientID, ProgramID};
+    use crate::config::Config;
+    use crate::jobs::{JobKind, JobStatus};
+
+    #[tokio::test]
+    async fn test_list_jobs_pagination() {
+        let state = AppState::new(&Config::default());
+        for block in 0..3 {
+            state
+                .jobs
+                .submit(
+                    JobKind::Execute,
+                    ProgramID("sp1".to_string()),
+                    None,
+                    ClientID::default(),
+                    Some(block),
+                    None,
+                )
+                .executed(100);
+        }
+
+        let first = list_jobs(
+            State(state.clone()),
+            Query(JobFilter::default()),
+            Query(PageParams {
+                cursor: None,
+                limit: Some(2),
+            }),
+        )
+        .await
+        .0;
+        assert_eq!(first.jobs.len(), 2);
+        assert!(first.next_cursor.is_some());
+
+        let second = list_jobs(
+            State(state),
+            Query(JobFilter {
+                status: Some(JobStatus::Succeeded),
+                ..Default::default()
+            }),
+            Query(PageParams {
+                cursor: first.next_cursor,
+                limit: Some(2),
+            }),
+        )
+        .await
+        .0;
+        assert_eq!(second.jobs.len(), 1);
+        assert_eq!(second.jobs[0].block_number, Some(0));
+        assert!(second.next_cursor.is_none());
+    }
+}
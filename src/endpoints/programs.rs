@@ -0,0 +1,163 @@
+//! Inspection of the ELFs behind registered programs.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+
+use crate::common::{AppState, ProgramID};
+use crate::program::{ElfInfo, inspect_elf};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VersionParams {
+    /// Inspect this version instead of the one the program points at
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProgramElfResponse {
+    pub program_id: ProgramID,
+    pub version: String,
+    pub elf: ElfInfo,
+}
+
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_program_elf(
+    State(state): State<AppState>,
+    Path(program_id): Path<ProgramID>,
+    Query(params): Query<VersionParams>,
+) -> Result<Json<ProgramElfResponse>, (StatusCode, String)> {
+    let (version, elf) = lookup_elf(&state, &program_id, params).await?;
+    let elf = inspect_elf(&elf).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to inspect ELF: {:#}", e),
+        )
+    })?;
+
+    Ok(Json(ProgramElfResponse {
+        program_id,
+        version,
+        elf,
+    }))
+}
+
+/// The raw ELF, as loaded by the server
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn download_program_elf(
+    State(state): State<AppState>,
+    Path(program_id): Path<ProgramID>,
+    Query(params): Query<VersionParams>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (version, elf) = lookup_elf(&state, &program_id, params).await?;
+    let disposition = format!("attachment; filename=\"{}-{}.elf\"", program_id.0, version);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        elf.to_vec(),
+    ))
+}
+
+async fn lookup_elf(
+    state: &AppState,
+    program_id: &ProgramID,
+    params: VersionParams,
+) -> Result<(String, Arc<[u8]>), (StatusCode, String)> {
+    let program = state.resolve(program_id, params.version.as_deref()).await?;
+    let elf = program
+        .instance
+        .elf
+        .ok_or((StatusCode::NOT_FOUND, "ELF not available".to_string()))?;
+    Ok((program.version, elf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{zkVMInstance, zkVMVendor};
+    use crate::config::Config;
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::{elf_digest, test_elf};
+
+    async fn state_with_elf() -> AppState {
+        let state = AppState::new(&Config::default());
+        let elf = test_elf();
+        let mut programs = state.programs.write().await;
+        programs.add_version(
+            elf_digest(&elf),
+            zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())).with_elf(elf.clone()),
+        );
+        programs.set_alias(ProgramID("guest".to_string()), &elf_digest(&elf));
+        programs.insert(
+            ProgramID("no_elf".to_string()),
+            zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+        );
+        drop(programs);
+        state
+    }
+
+    #[tokio::test]
+    async fn test_get_program_elf() {
+        let state = state_with_elf().await;
+
+        let response = get_program_elf(
+            State(state),
+            Path(ProgramID("guest".to_string())),
+            Query(VersionParams::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+
+        assert_eq!(response.version, elf_digest(&test_elf()));
+        assert_eq!(response.elf.digest, response.version);
+        assert_eq!(response.elf.entry, 0x1000);
+    }
+
+    #[tokio::test]
+    async fn test_download_program_elf() {
+        let state = state_with_elf().await;
+
+        let response = download_program_elf(
+            State(state),
+            Path(ProgramID("guest".to_string())),
+            Query(VersionParams::default()),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body.as_ref(), test_elf().as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_program_without_elf() {
+        let state = state_with_elf().await;
+
+        let (status, message) = get_program_elf(
+            State(state),
+            Path(ProgramID("no_elf".to_string())),
+            Query(VersionParams::default()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(message, "ELF not available");
+    }
+}
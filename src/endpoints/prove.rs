@@ -1,63 +1,220 @@
 use axum::{Json, extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
+use uuid::Uuid;
 use zkvm_interface::{Input, zkVM};
 
-use crate::common::{AppState, ProgramID};
-use crate::program::ProgramInput;
+use crate::common::{AppState, ClientID, ProgramID, ResolvedProgram};
+use crate::jobs::{JobHandle, JobKind, JobSummary};
+use crate::program::{NativeValidation, ValidationOutcome};
+use crate::scheduler::{Priority, Ticket};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProveRequest {
     pub program_id: ProgramID,
-    pub input: ProgramInput,
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Decoded according to the input kind of the program
+    pub input: serde_json::Value,
+    /// Where the proof is placed in the queue relative to other waiting work
+    #[serde(default)]
+    pub priority: Priority,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProveResponse {
+    pub job_id: Uuid,
     pub program_id: ProgramID,
+    /// Version the proof was generated with
+    pub version: String,
     pub proof: Vec<u8>,
     pub proving_time_milliseconds: u128,
+    /// Whether the proven blocks are valid, as committed by the guest, for programs taking a
+    /// `ClientInput`
+    #[serde(flatten)]
+    pub outcome: Option<ValidationOutcome>,
+    /// Outcome and statistics of the native validation of the blocks, when enabled
+    pub native: Option<NativeValidation>,
+    /// The native outcome differs from the one the guest committed
+    #[serde(default)]
+    pub native_mismatch: bool,
 }
 
 #[axum::debug_handler]
 #[instrument(skip_all)]
 pub async fn prove_program(
     State(state): State<AppState>,
-    Json(req): Json<ProveRequest>,
+    client_id: ClientID,
+    Json(mut req): Json<ProveRequest>,
 ) -> Result<Json<ProveResponse>, (StatusCode, String)> {
-    let program_id = req.program_id.clone();
-    let programs = state.programs.read().await;
+    state.lifecycle.admit().await?;
 
-    let program = programs
-        .get(&program_id)
-        .ok_or((StatusCode::NOT_FOUND, "Program not found".to_string()))?;
+    let program = state
+        .resolve(&req.program_id, req.version.as_deref())
+        .await?;
+    // Pin the version, so a resumed proof runs on the same program
+    req.version = Some(program.version.clone());
+    let input = program.decode_input(&req.input)?;
+    let native = state.pre_execute(&input).await?;
 
-    let input: Input = req.input.into();
-
-    let (proof, report) = program.vm.prove(&input).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to generate proof: {}", e),
-        )
+    let job = state.jobs.submit(
+        JobKind::Prove,
+        req.program_id.clone(),
+        req.version.clone(),
+        client_id.clone(),
+        input.block_number(),
+        Some(input.digest()),
+    );
+    // Record the request before any work starts, so it survives a restart
+    let (jobs, job_id) = (state.jobs.clone(), job.job_id());
+    let (req, saved) = tokio::task::spawn_blocking(move || {
+        let saved = jobs.save_input(job_id, &req);
+        (req, saved)
+    })
+    .await
+    .map_err(|e| {
+        let message = format!("Persisting task failed: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, message)
     })?;
+    if let Err(e) = saved {
+        let message = format!("Failed to persist prove request: {}", e);
+        job.failed(message.clone());
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, message));
+    }
+
+    let mut response = run_proof(&state, job, program, req, input.to_input(), client_id).await?;
+    let mismatch = native
+        .as_ref()
+        .zip(response.outcome.as_ref())
+        .filter(|(native, outcome)| native.outcome != **outcome);
+    if let Some((native, outcome)) = mismatch {
+        tracing::warn!(
+            "Prove job {} committed {:?}, but the host validation found {:?}",
+            response.job_id,
+            outcome,
+            native.outcome
+        );
+        response.native_mismatch = true;
+    }
+    response.native = native;
+    Ok(Json(response))
+}
+
+/// Resume a prove job that was interrupted by a restart, from its persisted request
+pub async fn resume_proof(state: AppState, job: JobSummary) {
+    let Some(handle) = state.jobs.reopen(job.job_id) else {
+        return;
+    };
+    let req: ProveRequest = match state.jobs.load_input(job.job_id) {
+        Ok(req) => req,
+        Err(e) => {
+            handle.failed(format!("Failed to load persisted prove request: {}", e));
+            return;
+        }
+    };
+    let resolved = state
+        .resolve(&req.program_id, req.version.as_deref())
+        .await
+        .and_then(|program| {
+            let input = program.decode_input(&req.input)?;
+            Ok((program, input))
+        });
+    let (program, input) = match resolved {
+        Ok(resolved) => resolved,
+        Err((_, message)) => {
+            handle.failed(message);
+            return;
+        }
+    };
+
+    tracing::info!("Resuming prove job {}", job.job_id);
+    let input = input.to_input();
+    if let Err((_, message)) = run_proof(&state, handle, program, req, input, job.client_id).await {
+        tracing::warn!("Resumed prove job {} failed: {}", job.job_id, message);
+    }
+}
+
+/// Wait for a proving slot, then generate the proof of a recorded job
+async fn run_proof(
+    state: &AppState,
+    job: JobHandle,
+    program: ResolvedProgram,
+    req: ProveRequest,
+    input: Input,
+    client_id: ClientID,
+) -> Result<ProveResponse, (StatusCode, String)> {
+    let job_id = job.job_id();
+    let program_id = req.program_id;
+
+    let permit = state
+        .scheduler
+        .acquire(Ticket {
+            job_id,
+            client_id,
+            program_id: program_id.clone(),
+            priority: req.priority,
+        })
+        .await;
+    job.start();
+
+    // The permit and the job move into the blocking task, so the slot stays taken and the
+    // proof is still recorded and stored if this request is dropped while it is generated
+    let vm = program.instance.vm.clone();
+    let (program, proof, report, outcome) = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        match vm.prove(&input) {
+            Ok((proof, report)) => {
+                // The proof is stored either way, so a public value that fails to decode is
+                // only reported
+                let outcome = program.committed_outcome(&proof).unwrap_or_else(|e| {
+                    tracing::warn!("Prove job {}: {}", job_id, e);
+                    None
+                });
+                let first_block = outcome.as_ref().map(|o| o.first_block_number);
+                job.proved(report.proving_time.as_millis(), &proof, first_block);
+                Ok((program, proof, report, outcome))
+            }
+            Err(e) => {
+                let message = format!("Failed to generate proof: {}", e);
+                job.failed(message.clone());
+                Err(message)
+            }
+        }
+    })
+    .await
+    .map_err(|e| format!("Proving task failed: {}", e))
+    .and_then(|result| result)
+    .map_err(|message| (StatusCode::INTERNAL_SERVER_ERROR, message))?;
 
-    Ok(Json(ProveResponse {
+    Ok(ProveResponse {
+        job_id,
         program_id,
+        version: program.version,
         proof,
         proving_time_milliseconds: report.proving_time.as_millis(),
-    }))
+        outcome,
+        native: None,
+        native_mismatch: false,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::common::{zkVMInstance, zkVMVendor};
-    use crate::mock_zkvm::MockZkVM;
-    use std::collections::HashMap;
+    use crate::config::Config;
+    use crate::config::{LeftoverPolicy, MockConfig};
+    use crate::jobs::{JobFilter, JobStatus, JobStore};
+    use crate::mock_zkvm::{MockZkVM, mock_block_validation, mock_public_values};
+    use crate::program::{
+        InputKind, ProgramInput, test_block_range, test_input, validate_natively,
+    };
+    use alloy_primitives::B256;
     use std::fs;
     use std::sync::Arc;
+    use std::time::Duration;
     use tempfile::TempDir;
-    use tokio::sync::RwLock;
 
     // Helper function to create a test AppState
     fn create_test_state() -> (AppState, TempDir) {
@@ -65,9 +222,7 @@ mod tests {
         let programs_dir = temp_dir.path().join("programs");
         fs::create_dir_all(&programs_dir).unwrap();
 
-        let state = AppState {
-            programs: Arc::new(RwLock::new(HashMap::new())),
-        };
+        let state = AppState::new(&Config::default());
 
         (state, temp_dir)
     }
@@ -88,10 +243,12 @@ mod tests {
 
         let request = ProveRequest {
             program_id: program_id.clone(),
-            input: ProgramInput::test_input(),
+            version: None,
+            input: test_input(),
+            priority: Priority::default(),
         };
 
-        let result = prove_program(State(state), Json(request)).await;
+        let result = prove_program(State(state), ClientID::default(), Json(request)).await;
 
         assert!(result.is_ok());
         let response = result.unwrap().0;
@@ -99,20 +256,205 @@ mod tests {
         assert!(!response.proof.is_empty()); // Check that the proof is not empty
     }
 
+    /// A state proving with a mock that commits `outcome`, as the guest would
+    async fn committing_state(config: &Config, outcome: &ValidationOutcome) -> AppState {
+        let state = AppState::new(config).with_block_validator(mock_block_validation);
+        let vm = MockZkVM::default().committing(bincode::serialize(outcome).unwrap());
+        state.programs.write().await.insert(
+            ProgramID("guest".to_string()),
+            zkVMInstance::new(zkVMVendor::Mock, Arc::new(vm))
+                .with_public_values(mock_public_values),
+        );
+        state
+    }
+
+    fn request(input: serde_json::Value) -> Json<ProveRequest> {
+        Json(ProveRequest {
+            program_id: ProgramID("guest".to_string()),
+            version: None,
+            input,
+            priority: Priority::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_prove_block_range() {
+        let config = Config {
+            native_validation: true,
+            ..Config::default()
+        };
+        let blocks = ProgramInput::decode(InputKind::ClientInput, &test_block_range()).unwrap();
+        let (blocks, chain) = blocks.blocks().unwrap();
+        let (committed, _) = validate_natively(blocks, chain, mock_block_validation).unwrap();
+        let state = committing_state(&config, &committed).await;
+
+        let response = prove_program(
+            State(state),
+            ClientID::default(),
+            request(test_block_range()),
+        )
+        .await
+        .unwrap()
+        .0;
+
+        let outcome = response.outcome.unwrap();
+        assert!(outcome.valid);
+        assert_eq!(outcome, committed);
+        let native = response.native.unwrap();
+        assert_eq!(native.report.block_count, 2);
+        assert!(!response.native_mismatch);
+        let json = serde_json::to_value(&outcome).unwrap();
+        assert_eq!(
+            json["parent_hash"],
+            test_input()["block"]["header"]["parentHash"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_native_mismatch() {
+        // The guest commits an invalid outcome for a block the host finds valid
+        let committed = ValidationOutcome {
+            valid: false,
+            block_hash: None,
+            ..ValidationOutcome::valid(1, 42, B256::ZERO, B256::ZERO)
+        };
+        let config = Config {
+            native_validation: true,
+            ..Config::default()
+        };
+        let state = committing_state(&config, &committed).await;
+
+        let response = prove_program(State(state), ClientID::default(), request(test_input()))
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(response.outcome, Some(committed.clone()));
+        assert!(response.native.unwrap().outcome.valid);
+        assert!(response.native_mismatch);
+
+        // Without native validation, only the committed outcome is reported
+        let state = committing_state(&Config::default(), &committed).await;
+        let response = prove_program(
+            State(state.clone()),
+            ClientID::default(),
+            request(test_input()),
+        )
+        .await
+        .unwrap()
+        .0;
+        assert_eq!(response.outcome, Some(committed));
+        assert!(response.native.is_none());
+        assert!(!response.native_mismatch);
+        // The job reports the first block the proof committed to, not the one of the input
+        let job = state.jobs.get(response.job_id).unwrap();
+        assert_eq!(job.block_number, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_proof_of_dropped_request_is_stored() {
+        let temp_dir = TempDir::new().unwrap();
+        let (jobs, _) = JobStore::open(temp_dir.path(), 100).unwrap();
+        let state = AppState::new(&Config::default()).with_jobs(jobs);
+        let vm = MockZkVM::new(MockConfig {
+            prove_latency: Duration::from_millis(200),
+            ..MockConfig::default()
+        });
+        state.programs.write().await.insert(
+            ProgramID("guest".to_string()),
+            zkVMInstance::new(zkVMVendor::Mock, Arc::new(vm)),
+        );
+
+        let request = tokio::spawn(prove_program(
+            State(state.clone()),
+            ClientID::default(),
+            request(test_input()),
+        ));
+        let running = JobFilter {
+            status: Some(JobStatus::Running),
+            ..JobFilter::default()
+        };
+        let job_id = loop {
+            if let Some(job) = state.jobs.list(&running, None, 1).jobs.pop() {
+                break job.job_id;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        // The client disconnects while the proof is being generated
+        request.abort();
+        let _ = request.await;
+
+        while !state.jobs.get(job_id).unwrap().status.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.jobs.get(job_id).unwrap().status, JobStatus::Succeeded);
+        assert!(state.jobs.proof(job_id).unwrap().is_some());
+    }
+
     #[tokio::test]
     async fn test_prove_program_not_found() {
         let (state, _temp_dir) = create_test_state();
 
         let request = ProveRequest {
             program_id: ProgramID("non_existent".to_string()),
-            input: ProgramInput::test_input(),
+            version: None,
+            input: test_input(),
+            priority: Priority::default(),
         };
 
-        let result = prove_program(State(state), Json(request)).await;
+        let result = prove_program(State(state), ClientID::default(), Json(request)).await;
 
         assert!(result.is_err());
         let (status, message) = result.unwrap_err();
         assert_eq!(status, StatusCode::NOT_FOUND);
         assert_eq!(message, "Program not found");
     }
+
+    #[tokio::test]
+    async fn test_resume_interrupted_proof() {
+        let temp_dir = TempDir::new().unwrap();
+        let program_id = ProgramID::from(zkVMVendor::SP1);
+
+        // Accept a proof, then shut down before it runs
+        let (jobs, _) = JobStore::open(temp_dir.path(), 100).unwrap();
+        let state = AppState::new(&Config::default()).with_jobs(jobs);
+        let request = ProveRequest {
+            program_id: program_id.clone(),
+            version: None,
+            input: test_input(),
+            priority: Priority::default(),
+        };
+        let input = ProgramInput::decode(InputKind::ClientInput, &request.input).unwrap();
+        let job = state.jobs.submit(
+            JobKind::Prove,
+            program_id.clone(),
+            None,
+            ClientID::default(),
+            None,
+            Some(input.digest()),
+        );
+        let job_id = job.job_id();
+        state.jobs.save_input(job_id, &request).unwrap();
+        crate::lifecycle::drain(&state, Duration::ZERO, LeftoverPolicy::Persist).await;
+        drop(job);
+        drop(state);
+
+        let (jobs, interrupted) = JobStore::open(temp_dir.path(), 100).unwrap();
+        let state = AppState::new(&Config::default()).with_jobs(jobs);
+        {
+            let mut programs = state.programs.write().await;
+            programs.insert(
+                program_id.clone(),
+                zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+            );
+        }
+        assert_eq!(interrupted.len(), 1);
+        resume_proof(state.clone(), interrupted.into_iter().next().unwrap()).await;
+
+        let job = state.jobs.get(job_id).unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(
+            state.jobs.proof(job_id).unwrap().unwrap(),
+            crate::mock_zkvm::mock_proof(&input.to_input(), &[])
+        );
+    }
 }
@@ -0,0 +1,61 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::common::AppState;
+use crate::lifecycle::Mode;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Readiness {
+    Ready,
+    Paused,
+    Draining,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    pub status: Readiness,
+}
+
+/// Readiness probe for load balancers and orchestrators.
+/// Returns 503 while the server is paused or no longer accepts new work.
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn get_readiness(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let (code, status) = match state.lifecycle.mode() {
+        Mode::Running => (StatusCode::OK, Readiness::Ready),
+        Mode::Paused => (StatusCode::SERVICE_UNAVAILABLE, Readiness::Paused),
+        Mode::Draining | Mode::ShuttingDown => {
+            (StatusCode::SERVICE_UNAVAILABLE, Readiness::Draining)
+        }
+    };
+    (code, Json(ReadinessResponse { status }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn test_readiness_reports_drain() {
+        let state = AppState::new(&Config::default());
+
+        let (status, response) = get_readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.status, Readiness::Ready);
+
+        state.lifecycle.pause().unwrap();
+
+        let (status, response) = get_readiness(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.status, Readiness::Paused);
+
+        state.lifecycle.begin_shutdown();
+
+        let (status, response) = get_readiness(State(state)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.status, Readiness::Draining);
+    }
+}
@@ -0,0 +1,163 @@
+//! Checking a request input without running the program.
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::common::{AppState, ProgramID};
+use crate::program::{InputError, InputKind, NativeReport, NativeValidation, ProgramInput};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidateInputRequest {
+    pub program_id: ProgramID,
+    /// Check against an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
+    pub input: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidateInputResponse {
+    pub program_id: ProgramID,
+    pub version: String,
+    pub kind: InputKind,
+    pub valid: bool,
+    /// Empty when the input is valid
+    pub errors: Vec<InputError>,
+    /// Only set for a valid input
+    pub digest: Option<String>,
+    pub block_number: Option<u64>,
+    /// Outcome of the native validation of the block, when it ran
+    pub native: Option<NativeReport>,
+}
+
+/// Decode and check an input the way `/execute` and `/prove` do, reporting every problem found
+#[axum::debug_handler]
+#[instrument(skip_all)]
+pub async fn validate_input(
+    State(state): State<AppState>,
+    Json(req): Json<ValidateInputRequest>,
+) -> Result<Json<ValidateInputResponse>, (StatusCode, String)> {
+    let program = state
+        .resolve(&req.program_id, req.version.as_deref())
+        .await?;
+    let kind = program.instance.input;
+
+    let (mut errors, input) = match ProgramInput::decode(kind, &req.input) {
+        Ok(input) => (input.validate(), Some(input)),
+        Err(e) => (vec![e], None),
+    };
+    let input = input.filter(|_| errors.is_empty());
+    let mut native = None;
+    if let Some(input) = &input {
+        // Validating is the point of this endpoint, so it runs whether or not it is enabled
+        // before executing and proving
+        match state.validate_natively(input).await {
+            Ok(Some(NativeValidation { outcome, report })) => {
+                if let Some(reason) = outcome.reason {
+                    let path = match input {
+                        ProgramInput::BlockRange(blocks, _) => {
+                            let first = blocks[0].block.header.number;
+                            format!("input.blocks[{}].block", reason.block_number - first)
+                        }
+                        _ => "input.block".to_string(),
+                    };
+                    errors.push(InputError::new(
+                        path,
+                        format!("{}: {}", reason.code, reason.message),
+                    ));
+                }
+                native = Some(report);
+            }
+            Ok(None) => {}
+            Err((_, message)) => errors.push(InputError::new("input.block", message)),
+        }
+    }
+    let input = input.filter(|_| errors.is_empty());
+
+    Ok(Json(ValidateInputResponse {
+        program_id: req.program_id,
+        version: program.version,
+        kind,
+        valid: errors.is_empty(),
+        errors,
+        digest: input.as_ref().map(ProgramInput::digest),
+        block_number: input.as_ref().and_then(ProgramInput::block_number),
+        native,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{zkVMInstance, zkVMVendor};
+    use crate::config::Config;
+    use crate::mock_zkvm::{MockZkVM, mock_block_validation};
+    use crate::program::test_input;
+    use std::sync::Arc;
+
+    async fn state() -> AppState {
+        let state = AppState::new(&Config::default()).with_block_validator(mock_block_validation);
+        state.programs.write().await.insert(
+            ProgramID("guest".to_string()),
+            zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+        );
+        state
+    }
+
+    fn request(input: serde_json::Value) -> Json<ValidateInputRequest> {
+        Json(ValidateInputRequest {
+            program_id: ProgramID("guest".to_string()),
+            version: None,
+            input,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_decode_error() {
+        let mut input = test_input();
+        input["witness"]["codes"] = serde_json::json!(["0x1"]);
+
+        let response = validate_input(State(state().await), request(input))
+            .await
+            .unwrap()
+            .0;
+
+        assert!(!response.valid);
+        assert_eq!(response.kind, InputKind::ClientInput);
+        assert_eq!(
+            response.errors,
+            vec![InputError::new("input.witness.codes[0]", "invalid hex")]
+        );
+        assert_eq!(response.digest, None);
+    }
+
+    #[tokio::test]
+    async fn test_preflight_errors() {
+        let mut input = test_input();
+        input["witness"]["state"] = serde_json::json!([]);
+        input["witness"]["headers"] = serde_json::json!([]);
+
+        let response = validate_input(State(state().await), request(input))
+            .await
+            .unwrap()
+            .0;
+
+        assert!(!response.valid);
+        let paths: Vec<&str> = response.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["input.witness.state", "input.witness.headers"]);
+    }
+
+    #[tokio::test]
+    async fn test_valid_input() {
+        let response = validate_input(State(state().await), request(test_input()))
+            .await
+            .unwrap()
+            .0;
+
+        assert!(response.valid);
+        assert!(response.errors.is_empty());
+        assert_eq!(response.block_number, Some(10));
+        assert!(response.digest.is_some());
+    }
+}
@@ -4,26 +4,21 @@ use tracing::instrument;
 use zkvm_interface::zkVM;
 
 use crate::common::{AppState, ProgramID};
-use crate::{
-    common::{zkVMInstance, zkVMVendor},
-    endpoints::{prove::ProveRequest, prove_program},
-    program::ProgramInput,
-};
-
-use std::collections::HashMap;
-use std::fs;
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyRequest {
     pub program_id: ProgramID,
+    /// Pin an exact program version instead of the one `program_id` points at
+    #[serde(default)]
+    pub version: Option<String>,
     pub proof: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyResponse {
     pub program_id: ProgramID,
+    /// Version the proof was verified against
+    pub version: String,
     pub verified: bool,
     // Empty if verification returned true
     pub failure_reason: String,
@@ -36,20 +31,19 @@ pub async fn verify_proof(
     Json(req): Json<VerifyRequest>,
 ) -> Result<Json<VerifyResponse>, (StatusCode, String)> {
     // Check if the program_id is correct
-    let programs = state.programs.read().await;
-
-    let program = programs
-        .get(&req.program_id)
-        .ok_or((StatusCode::NOT_FOUND, "Program not found".to_string()))?;
+    let program = state
+        .resolve(&req.program_id, req.version.as_deref())
+        .await?;
 
     // Verify the proof
-    let (verified, failure_reason) = match program.vm.verify(&req.proof) {
+    let (verified, failure_reason) = match program.instance.vm.verify(&req.proof) {
         Ok(_) => (true, String::default()),
         Err(err) => (false, format!("{}", err)),
     };
 
     Ok(Json(VerifyResponse {
         program_id: req.program_id,
+        version: program.version,
         verified,
         failure_reason,
     }))
@@ -58,12 +52,15 @@ pub async fn verify_proof(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::{ClientID, zkVMInstance, zkVMVendor};
+    use crate::config::Config;
+    use crate::endpoints::{prove::ProveRequest, prove_program};
     use crate::mock_zkvm::MockZkVM;
-    use std::collections::HashMap;
+    use crate::program::test_input;
+    use crate::scheduler::Priority;
     use std::fs;
     use std::sync::Arc;
     use tempfile::TempDir;
-    use tokio::sync::RwLock;
 
     // Helper function to create a test AppState
     fn create_test_state() -> (AppState, TempDir) {
@@ -71,9 +68,7 @@ mod tests {
         let programs_dir = temp_dir.path().join("programs");
         fs::create_dir_all(&programs_dir).unwrap();
 
-        let state = AppState {
-            programs: Arc::new(RwLock::new(HashMap::new())),
-        };
+        let state = AppState::new(&Config::default());
 
         (state, temp_dir)
     }
@@ -83,9 +78,7 @@ mod tests {
         let program_id = ProgramID::from(zkVMVendor::SP1);
         let mock_zkvm = MockZkVM::default();
 
-        let state = AppState {
-            programs: Arc::new(RwLock::new(HashMap::new())),
-        };
+        let state = AppState::new(&Config::default());
         {
             let mut programs = state.programs.write().await;
             programs.insert(
@@ -96,16 +89,19 @@ mod tests {
 
         let request = ProveRequest {
             program_id: program_id.clone(),
-            input: ProgramInput::test_input(),
+            version: None,
+            input: test_input(),
+            priority: Priority::default(),
         };
 
-        let result = prove_program(State(state.clone()), Json(request))
+        let result = prove_program(State(state.clone()), ClientID::default(), Json(request))
             .await
             .unwrap();
 
         // Create a request
         let request = VerifyRequest {
             program_id: result.program_id.clone(),
+            version: Some(result.version.clone()),
             proof: result.proof.clone(),
         };
 
@@ -133,6 +129,7 @@ mod tests {
 
         let request = VerifyRequest {
             program_id: program_id.clone(),
+            version: None,
             proof: b"invalid_proof".to_vec(),
         };
 
@@ -150,6 +147,7 @@ mod tests {
 
         let request = VerifyRequest {
             program_id: ProgramID("non_existent".to_string()),
+            version: None,
             proof: b"example_proof".to_vec(),
         };
 
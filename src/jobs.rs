@@ -0,0 +1,678 @@
+//! History of every execution and proof the server has run.
+
+mod log;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::common::{ClientID, ProgramID};
+use log::JobLog;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Execute,
+    Prove,
+    /// Repeated executions and proofs, see `/benchmark`
+    Benchmark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting in the scheduler queue
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// The request went away before the job finished
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// Summary of one job. Timestamps are milliseconds since the unix epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub job_id: Uuid,
+    /// Position in submission order, which doubles as the pagination cursor
+    #[serde(default)]
+    pub seq: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub program_id: ProgramID,
+    /// Program version the job ran on
+    #[serde(default)]
+    pub version: Option<String>,
+    pub client_id: ClientID,
+    pub block_number: Option<u64>,
+    /// SHA-256 of the program input
+    #[serde(default)]
+    pub input_hash: Option<String>,
+    pub submitted_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub total_num_cycles: Option<u64>,
+    pub proving_time_milliseconds: Option<u128>,
+    pub proof_size: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Filters for listing jobs. Every field is optional.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    pub kind: Option<JobKind>,
+    pub program_id: Option<ProgramID>,
+    pub client_id: Option<ClientID>,
+    pub block_number: Option<u64>,
+    /// Only jobs submitted at or after this time
+    pub since: Option<u64>,
+    /// Only jobs submitted before this time
+    pub until: Option<u64>,
+}
+
+impl JobFilter {
+    fn matches(&self, job: &JobSummary) -> bool {
+        self.status.is_none_or(|status| job.status == status)
+            && self.kind.is_none_or(|kind| job.kind == kind)
+            && self
+                .program_id
+                .as_ref()
+                .is_none_or(|id| &job.program_id == id)
+            && self
+                .client_id
+                .as_ref()
+                .is_none_or(|id| &job.client_id == id)
+            && self
+                .block_number
+                .is_none_or(|number| job.block_number == Some(number))
+            && self.since.is_none_or(|since| job.submitted_at >= since)
+            && self.until.is_none_or(|until| job.submitted_at < until)
+    }
+}
+
+/// One page of jobs, newest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobPage {
+    pub jobs: Vec<JobSummary>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_seq: u64,
+    // Keyed by submission order, which doubles as the pagination cursor
+    by_seq: BTreeMap<u64, JobSummary>,
+    seq_by_id: HashMap<Uuid, u64>,
+}
+
+pub struct JobStore {
+    jobs: Mutex<Jobs>,
+    // Oldest finished jobs are dropped beyond this many records
+    capacity: usize,
+    // Without a log, jobs only live in memory
+    log: Option<JobLog>,
+    // Set once the process is stopping, so dropped handles leave their jobs unfinished
+    detached: AtomicBool,
+}
+
+impl JobStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            jobs: Mutex::new(Jobs::default()),
+            capacity,
+            log: None,
+            detached: AtomicBool::new(false),
+        }
+    }
+
+    /// Open a store persisted in `dir`, restoring the jobs recorded there.
+    ///
+    /// Returns the store along with the jobs that had not finished when the
+    /// server stopped. They are left untouched, so the caller decides whether
+    /// to resume them or mark them as failed.
+    pub fn open(dir: &Path, capacity: usize) -> io::Result<(Self, Vec<JobSummary>)> {
+        let (log, restored) = JobLog::open(dir)?;
+        let mut jobs = Jobs::default();
+        let mut unfinished = Vec::new();
+        for mut job in restored {
+            // Logs written before jobs recorded their position are numbered in order
+            let seq = job.seq.max(jobs.next_seq);
+            job.seq = seq;
+            jobs.next_seq = seq + 1;
+            if !job.status.is_finished() {
+                unfinished.push(job.clone());
+            }
+            jobs.seq_by_id.insert(job.job_id, seq);
+            jobs.by_seq.insert(seq, job);
+        }
+
+        let store = Self {
+            jobs: Mutex::new(Jobs::default()),
+            capacity,
+            log: Some(log),
+            detached: AtomicBool::new(false),
+        };
+        store.evict(&mut jobs);
+        *store.lock() = jobs;
+        Ok((store, unfinished))
+    }
+
+    /// Record a new job. Execute jobs start running immediately, prove and benchmark jobs are
+    /// queued.
+    pub fn submit(
+        self: &Arc<Self>,
+        kind: JobKind,
+        program_id: ProgramID,
+        version: Option<String>,
+        client_id: ClientID,
+        block_number: Option<u64>,
+        input_hash: Option<String>,
+    ) -> JobHandle {
+        let job_id = Uuid::new_v4();
+        let now = now_millis();
+        let (status, started_at) = match kind {
+            JobKind::Execute => (JobStatus::Running, Some(now)),
+            JobKind::Prove | JobKind::Benchmark => (JobStatus::Queued, None),
+        };
+        let mut jobs = self.lock();
+        let seq = jobs.next_seq;
+        jobs.next_seq += 1;
+        let summary = JobSummary {
+            job_id,
+            seq,
+            kind,
+            status,
+            program_id,
+            version,
+            client_id,
+            block_number,
+            input_hash,
+            submitted_at: now,
+            started_at,
+            finished_at: None,
+            total_num_cycles: None,
+            proving_time_milliseconds: None,
+            proof_size: None,
+            error: None,
+        };
+
+        self.persist(&summary);
+        jobs.by_seq.insert(seq, summary);
+        jobs.seq_by_id.insert(job_id, seq);
+        self.evict(&mut jobs);
+        drop(jobs);
+
+        JobHandle {
+            store: Arc::clone(self),
+            job_id,
+            finished: false,
+        }
+    }
+
+    /// Take over an unfinished job restored by `open`, putting it back in the queue
+    pub fn reopen(self: &Arc<Self>, job_id: Uuid) -> Option<JobHandle> {
+        let mut resumed = false;
+        self.update(job_id, |job| {
+            if !job.status.is_finished() {
+                job.status = JobStatus::Queued;
+                job.started_at = None;
+                resumed = true;
+            }
+        });
+        resumed.then(|| JobHandle {
+            store: Arc::clone(self),
+            job_id,
+            finished: false,
+        })
+    }
+
+    /// Durably record the request of a job, so it can be resumed after a restart
+    pub fn save_input<T: Serialize>(&self, job_id: Uuid, input: &T) -> io::Result<()> {
+        match &self.log {
+            Some(log) => log.save_input(job_id, input),
+            None => Ok(()),
+        }
+    }
+
+    pub fn load_input<T: DeserializeOwned>(&self, job_id: Uuid) -> io::Result<T> {
+        match &self.log {
+            Some(log) => log.load_input(job_id),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "job inputs are not persisted",
+            )),
+        }
+    }
+
+    /// Proof of a successful prove job, if it is still stored
+    pub fn proof(&self, job_id: Uuid) -> io::Result<Option<Vec<u8>>> {
+        match &self.log {
+            Some(log) => log.load_proof(job_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Jobs that are queued or running
+    pub fn unfinished(&self) -> Vec<JobSummary> {
+        let jobs = self.lock();
+        jobs.by_seq
+            .values()
+            .filter(|job| !job.status.is_finished())
+            .cloned()
+            .collect()
+    }
+
+    /// Stop cancelling the jobs of dropped handles. Once the process is stopping, requests
+    /// are dropped with their work unfinished, and their jobs stay in the job log as they
+    /// are, along with the persisted requests, for the recovery policy of the next start.
+    pub fn detach(&self) {
+        self.detached.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait until every change to the jobs so far is on disk
+    pub async fn flush(&self) {
+        if let Some(log) = &self.log {
+            log.flush().await;
+        }
+    }
+
+    /// Number of jobs that are running
+    pub fn running(&self) -> usize {
+        let jobs = self.lock();
+        jobs.by_seq
+            .values()
+            .filter(|job| job.status == JobStatus::Running)
+            .count()
+    }
+
+    /// Mark an unfinished job as cancelled on behalf of the server
+    pub fn cancel(&self, job_id: Uuid, reason: String) {
+        self.update(job_id, |job| {
+            if !job.status.is_finished() {
+                job.status = JobStatus::Cancelled;
+                job.error = Some(reason);
+                job.finished_at = Some(now_millis());
+            }
+        });
+    }
+
+    pub fn get(&self, job_id: Uuid) -> Option<JobSummary> {
+        let jobs = self.lock();
+        let seq = jobs.seq_by_id.get(&job_id)?;
+        jobs.by_seq.get(seq).cloned()
+    }
+
+    /// List jobs matching `filter`, newest first, starting below `cursor`
+    pub fn list(&self, filter: &JobFilter, cursor: Option<u64>, limit: usize) -> JobPage {
+        let jobs = self.lock();
+        let upper = cursor.unwrap_or(u64::MAX);
+        let mut page: Vec<(u64, &JobSummary)> = jobs
+            .by_seq
+            .range(..upper)
+            .rev()
+            .filter(|(_, job)| filter.matches(job))
+            .take(limit + 1)
+            .map(|(seq, job)| (*seq, job))
+            .collect();
+
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|(seq, _)| *seq)
+        } else {
+            None
+        };
+
+        JobPage {
+            jobs: page.into_iter().map(|(_, job)| job.clone()).collect(),
+            next_cursor,
+        }
+    }
+
+    fn update(&self, job_id: Uuid, f: impl FnOnce(&mut JobSummary)) {
+        let mut jobs = self.lock();
+        let Some(seq) = jobs.seq_by_id.get(&job_id).copied() else {
+            return;
+        };
+        if let Some(job) = jobs.by_seq.get_mut(&seq) {
+            f(job);
+            self.persist(job);
+            match &self.log {
+                Some(log) if job.status.is_finished() => log.remove_input(job_id),
+                _ => {}
+            }
+        }
+    }
+
+    fn persist(&self, job: &JobSummary) {
+        if let Some(log) = &self.log {
+            log.append(job);
+        }
+    }
+
+    fn evict(&self, jobs: &mut Jobs) {
+        while jobs.by_seq.len() > self.capacity {
+            let Some(seq) = jobs
+                .by_seq
+                .iter()
+                .find(|(_, job)| job.status.is_finished())
+                .map(|(seq, _)| *seq)
+            else {
+                break;
+            };
+            if let Some(job) = jobs.by_seq.remove(&seq) {
+                jobs.seq_by_id.remove(&job.job_id);
+                if let Some(log) = &self.log {
+                    log.evict(job.job_id);
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Jobs> {
+        self.jobs.lock().expect("job store poisoned")
+    }
+}
+
+/// Updates the record of one job. A job whose handle is dropped before it
+/// finished is marked as cancelled, or as failed when dropped by a panic, unless
+/// the store was detached by a shutdown.
+pub struct JobHandle {
+    store: Arc<JobStore>,
+    job_id: Uuid,
+    finished: bool,
+}
+
+impl JobHandle {
+    pub fn job_id(&self) -> Uuid {
+        self.job_id
+    }
+
+    /// Mark a queued job as running
+    pub fn start(&self) {
+        self.store.update(self.job_id, |job| {
+            job.status = JobStatus::Running;
+            job.started_at = Some(now_millis());
+        });
+    }
+
+    pub fn executed(mut self, total_num_cycles: u64) {
+        self.finish(|job| {
+            job.status = JobStatus::Succeeded;
+            job.total_num_cycles = Some(total_num_cycles);
+        });
+    }
+
+    /// Record a successful proof. `block_number` is the first block the proof committed to, and
+    /// replaces the one taken from the input when present.
+    pub fn proved(
+        mut self,
+        proving_time_milliseconds: u128,
+        proof: &[u8],
+        block_number: Option<u64>,
+    ) {
+        let saved = self
+            .store
+            .log
+            .as_ref()
+            .map(|log| log.save_proof(self.job_id, proof));
+        if let Some(Err(e)) = saved {
+            tracing::error!("Failed to store proof of job {}: {}", self.job_id, e);
+        }
+        self.finish(|job| {
+            job.status = JobStatus::Succeeded;
+            job.proving_time_milliseconds = Some(proving_time_milliseconds);
+            job.proof_size = Some(proof.len());
+            if block_number.is_some() {
+                job.block_number = block_number;
+            }
+        });
+    }
+
+    /// Record the median cycles and proving time of a benchmark, for the modes it ran
+    pub fn benchmarked(
+        mut self,
+        total_num_cycles: Option<u64>,
+        proving_time_milliseconds: Option<u128>,
+    ) {
+        self.finish(|job| {
+            job.status = JobStatus::Succeeded;
+            job.total_num_cycles = total_num_cycles;
+            job.proving_time_milliseconds = proving_time_milliseconds;
+        });
+    }
+
+    /// Mark a job that stopped before it finished, for example by a shutdown
+    pub fn cancelled(mut self, reason: String) {
+        self.finish(|job| {
+            job.status = JobStatus::Cancelled;
+            job.error = Some(reason);
+        });
+    }
+
+    pub fn failed(mut self, error: String) {
+        self.finish(|job| {
+            job.status = JobStatus::Failed;
+            job.error = Some(error);
+        });
+    }
+
+    fn finish(&mut self, f: impl FnOnce(&mut JobSummary)) {
+        self.finished = true;
+        self.store.update(self.job_id, |job| {
+            f(job);
+            job.finished_at = Some(now_millis());
+        });
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        if self.finished || self.store.detached.load(Ordering::Relaxed) {
+            return;
+        }
+        // A handle dropped by a panic belongs to work that failed, not to a request that left
+        if std::thread::panicking() {
+            self.finish(|job| {
+                job.status = JobStatus::Failed;
+                job.error = Some("The job panicked".to_string());
+            });
+        } else {
+            self.finish(|job| job.status = JobStatus::Cancelled);
+        }
+    }
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submit(store: &Arc<JobStore>, kind: JobKind, client: &str, block: u64) -> JobHandle {
+        store.submit(
+            kind,
+            ProgramID("sp1".to_string()),
+            None,
+            ClientID(client.to_string()),
+            Some(block),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let store = Arc::new(JobStore::new(100));
+
+        let job = submit(&store, JobKind::Prove, "a", 1);
+        let job_id = job.job_id();
+        assert_eq!(store.get(job_id).unwrap().status, JobStatus::Queued);
+
+        job.start();
+        assert_eq!(store.get(job_id).unwrap().status, JobStatus::Running);
+
+        job.proved(42, b"proof!!", Some(7));
+        let summary = store.get(job_id).unwrap();
+        assert_eq!(summary.status, JobStatus::Succeeded);
+        assert_eq!(summary.proving_time_milliseconds, Some(42));
+        assert_eq!(summary.proof_size, Some(7));
+        assert_eq!(summary.block_number, Some(7));
+        assert!(summary.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_dropped_job_is_cancelled() {
+        let store = Arc::new(JobStore::new(100));
+
+        let job_id = submit(&store, JobKind::Prove, "a", 1).job_id();
+
+        assert_eq!(store.get(job_id).unwrap().status, JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_list_filters_and_paginates() {
+        let store = Arc::new(JobStore::new(100));
+        for block in 0..5 {
+            submit(&store, JobKind::Execute, "a", block).executed(block * 10);
+        }
+        submit(&store, JobKind::Execute, "b", 2).failed("boom".to_string());
+
+        let filter = JobFilter {
+            client_id: Some(ClientID("a".to_string())),
+            ..Default::default()
+        };
+        let first = store.list(&filter, None, 3);
+        let blocks: Vec<_> = first.jobs.iter().map(|j| j.block_number).collect();
+        assert_eq!(blocks, vec![Some(4), Some(3), Some(2)]);
+
+        let second = store.list(&filter, first.next_cursor, 3);
+        let blocks: Vec<_> = second.jobs.iter().map(|j| j.block_number).collect();
+        assert_eq!(blocks, vec![Some(1), Some(0)]);
+        assert!(second.next_cursor.is_none());
+
+        let failed = JobFilter {
+            status: Some(JobStatus::Failed),
+            block_number: Some(2),
+            ..Default::default()
+        };
+        let page = store.list(&failed, None, 10);
+        assert_eq!(page.jobs.len(), 1);
+        assert_eq!(page.jobs[0].error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn test_persisted_jobs_survive_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        let (store, unfinished) = JobStore::open(dir.path(), 100).unwrap();
+        assert!(unfinished.is_empty());
+        let store = Arc::new(store);
+
+        let done = submit(&store, JobKind::Prove, "a", 1);
+        let done_id = done.job_id();
+        done.proved(10, b"proof", None);
+
+        let interrupted = submit(&store, JobKind::Prove, "a", 2);
+        let interrupted_id = interrupted.job_id();
+        store
+            .save_input(interrupted_id, &"request".to_string())
+            .unwrap();
+        interrupted.start();
+        // The server shuts down before the job finishes, dropping its request
+        store.detach();
+        drop(interrupted);
+        drop(store);
+
+        let (store, unfinished) = JobStore::open(dir.path(), 100).unwrap();
+        assert_eq!(unfinished.len(), 1);
+        assert_eq!(unfinished[0].job_id, interrupted_id);
+        assert_eq!(unfinished[0].status, JobStatus::Running);
+        let input: String = store.load_input(interrupted_id).unwrap();
+        assert_eq!(input, "request");
+
+        assert_eq!(store.get(done_id).unwrap().status, JobStatus::Succeeded);
+        assert_eq!(store.proof(done_id).unwrap().unwrap(), b"proof");
+    }
+
+    #[test]
+    fn test_cursor_survives_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (store, _) = JobStore::open(dir.path(), 3).unwrap();
+        let store = Arc::new(store);
+        for block in 0..6 {
+            submit(&store, JobKind::Execute, "a", block).executed(1);
+        }
+        let first = store.list(&JobFilter::default(), None, 2);
+        let cursor = first.next_cursor;
+        let before = store.list(&JobFilter::default(), cursor, 10);
+        drop(store);
+
+        // Evicted jobs leave gaps that a renumbering would close
+        let (store, _) = JobStore::open(dir.path(), 3).unwrap();
+        let store = Arc::new(store);
+        let after = store.list(&JobFilter::default(), cursor, 10);
+        let ids = |page: &JobPage| page.jobs.iter().map(|j| j.job_id).collect::<Vec<_>>();
+        assert_eq!(ids(&after), ids(&before));
+
+        let next = submit(&store, JobKind::Execute, "a", 6);
+        assert_eq!(store.get(next.job_id()).unwrap().seq, 6);
+        next.executed(1);
+    }
+
+    #[tokio::test]
+    async fn test_log_is_compacted_as_it_grows() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (store, _) = JobStore::open(dir.path(), 10).unwrap();
+        let store = Arc::new(store);
+        for block in 0..2000 {
+            submit(&store, JobKind::Execute, "a", block).executed(1);
+        }
+        store.flush().await;
+
+        let lines = std::fs::read_to_string(dir.path().join("jobs.log"))
+            .unwrap()
+            .lines()
+            .count();
+        assert!(lines <= log::MIN_COMPACTION_LINES, "{lines} lines");
+        drop(store);
+
+        let (store, _) = JobStore::open(dir.path(), 10).unwrap();
+        let page = store.list(&JobFilter::default(), None, 100);
+        let blocks: Vec<_> = page.jobs.iter().map(|j| j.block_number).collect();
+        assert_eq!(blocks, (1990..2000).rev().map(Some).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_oldest_finished_jobs_are_evicted() {
+        let store = Arc::new(JobStore::new(2));
+        let running = submit(&store, JobKind::Execute, "a", 0);
+        for block in 1..4 {
+            submit(&store, JobKind::Execute, "a", block).executed(1);
+        }
+
+        let page = store.list(&JobFilter::default(), None, 10);
+        let blocks: Vec<_> = page.jobs.iter().map(|j| j.block_number).collect();
+        assert_eq!(blocks, vec![Some(3), Some(0)]);
+        running.executed(1);
+    }
+}
@@ -0,0 +1,312 @@
+//! On-disk record of jobs, kept in a data directory:
+//!
+//! - `jobs.log`: append-only JSON lines, one `JobSummary` snapshot per state change, compacted
+//!   to the latest snapshot of every job at startup and as it grows
+//! - `inputs/<job_id>.json`: the request of every accepted proof, written before it runs
+//! - `proofs/<job_id>.bin`: the proof of every successful proof job
+
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::JobSummary;
+
+const LOG_FILE: &str = "jobs.log";
+const INPUTS_DIR: &str = "inputs";
+const PROOFS_DIR: &str = "proofs";
+// The log is compacted once it holds twice as many lines as jobs, and at least this many
+pub(super) const MIN_COMPACTION_LINES: usize = 1024;
+
+/// Disk work handed to the writer thread, in the order the store made the changes
+enum Change {
+    Record(Box<JobSummary>),
+    /// Delete the input of a job that finished, once its final record is on disk
+    RemoveInput(Uuid),
+    /// Forget an evicted job and delete its files
+    Evict(Uuid),
+    Flush(oneshot::Sender<()>),
+}
+
+pub struct JobLog {
+    dir: PathBuf,
+    changes: Option<mpsc::Sender<Change>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl JobLog {
+    /// Open the log in `dir` and replay it.
+    ///
+    /// Returns the latest snapshot of every job, in submission order. The log is
+    /// rewritten to hold only those snapshots, and again whenever it grows well past
+    /// them, keeping it from growing forever. Records are written by a dedicated
+    /// thread, so the store never waits on the disk.
+    pub fn open(dir: &Path) -> io::Result<(Self, Vec<JobSummary>)> {
+        fs::create_dir_all(dir.join(INPUTS_DIR))?;
+        fs::create_dir_all(dir.join(PROOFS_DIR))?;
+
+        let path = dir.join(LOG_FILE);
+        let jobs = if path.exists() {
+            replay(&path)?
+        } else {
+            Vec::new()
+        };
+
+        let file = compact(&path, &jobs)?;
+        let mut writer = Writer {
+            path,
+            file,
+            lines: jobs.len(),
+            next_position: 0,
+            positions: HashMap::new(),
+            jobs: BTreeMap::new(),
+        };
+        for job in &jobs {
+            writer.remember(job.clone());
+        }
+
+        let (changes, received) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("job-log".to_string())
+            .spawn(move || writer.run(received))?;
+        let log = Self {
+            dir: dir.to_path_buf(),
+            changes: Some(changes),
+            writer: Some(writer),
+        };
+        Ok((log, jobs))
+    }
+
+    pub fn append(&self, job: &JobSummary) {
+        self.send(Change::Record(Box::new(job.clone())));
+    }
+
+    /// Wait until every change sent so far is on disk
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        self.send(Change::Flush(done));
+        let _ = flushed.await;
+    }
+
+    pub fn save_input<T: Serialize>(&self, job_id: Uuid, input: &T) -> io::Result<()> {
+        let bytes = serde_json::to_vec(input)?;
+        write_durably(&self.input_path(job_id), &bytes)
+    }
+
+    pub fn load_input<T: DeserializeOwned>(&self, job_id: Uuid) -> io::Result<T> {
+        let bytes = fs::read(self.input_path(job_id))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    pub fn save_proof(&self, job_id: Uuid, proof: &[u8]) -> io::Result<()> {
+        write_durably(&self.proof_path(job_id), proof)
+    }
+
+    pub fn load_proof(&self, job_id: Uuid) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.proof_path(job_id)) {
+            Ok(proof) => Ok(Some(proof)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forget an evicted job and delete its input and proof files
+    pub fn evict(&self, job_id: Uuid) {
+        self.send(Change::Evict(job_id));
+    }
+
+    /// Delete the input file of a job once it no longer needs to be resumed
+    pub fn remove_input(&self, job_id: Uuid) {
+        self.send(Change::RemoveInput(job_id));
+    }
+
+    fn send(&self, change: Change) {
+        let sent = self.changes.as_ref().map(|changes| changes.send(change));
+        if !matches!(sent, Some(Ok(()))) {
+            tracing::error!("The job log writer has stopped");
+        }
+    }
+
+    fn input_path(&self, job_id: Uuid) -> PathBuf {
+        input_path(&self.dir, job_id)
+    }
+
+    fn proof_path(&self, job_id: Uuid) -> PathBuf {
+        proof_path(&self.dir, job_id)
+    }
+}
+
+impl Drop for JobLog {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once it has written what is left
+        self.changes.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    // Lines in the log file since it was last compacted
+    lines: usize,
+    // Latest snapshot of every job, in the order the log first recorded them
+    next_position: u64,
+    positions: HashMap<Uuid, u64>,
+    jobs: BTreeMap<u64, JobSummary>,
+}
+
+impl Writer {
+    fn run(mut self, changes: mpsc::Receiver<Change>) {
+        while let Ok(first) = changes.recv() {
+            // Write everything that queued up meanwhile with a single sync
+            let mut removals = Vec::new();
+            let mut flushed = Vec::new();
+            let mut written = false;
+            for change in std::iter::once(first).chain(changes.try_iter()) {
+                match change {
+                    Change::Record(job) => {
+                        if let Err(e) = self.write(&job) {
+                            tracing::error!("Failed to persist job {}: {}", job.job_id, e);
+                        }
+                        self.remember(*job);
+                        written = true;
+                    }
+                    Change::RemoveInput(job_id) => removals.push((job_id, false)),
+                    Change::Evict(job_id) => {
+                        if let Some(position) = self.positions.remove(&job_id) {
+                            self.jobs.remove(&position);
+                        }
+                        removals.push((job_id, true));
+                    }
+                    Change::Flush(done) => flushed.push(done),
+                }
+            }
+
+            let synced = if written {
+                self.file.sync_data()
+            } else {
+                Ok(())
+            };
+            if let Err(e) = synced {
+                tracing::error!("Failed to sync {}: {}", self.path.display(), e);
+            }
+            let dir = self.path.parent().unwrap_or(Path::new("."));
+            for (job_id, evicted) in removals {
+                remove_if_exists(&input_path(dir, job_id));
+                if evicted {
+                    remove_if_exists(&proof_path(dir, job_id));
+                }
+            }
+            if self.lines > MIN_COMPACTION_LINES.max(2 * self.jobs.len()) {
+                let jobs: Vec<_> = self.jobs.values().cloned().collect();
+                match compact(&self.path, &jobs) {
+                    Ok(file) => {
+                        self.file = file;
+                        self.lines = jobs.len();
+                    }
+                    Err(e) => tracing::error!("Failed to compact {}: {}", self.path.display(), e),
+                }
+            }
+            for done in flushed {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    fn write(&mut self, job: &JobSummary) -> io::Result<()> {
+        let line = serde_json::to_string(job)?;
+        writeln!(self.file, "{line}")?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    fn remember(&mut self, job: JobSummary) {
+        let position = *self.positions.entry(job.job_id).or_insert_with(|| {
+            self.next_position += 1;
+            self.next_position
+        });
+        self.jobs.insert(position, job);
+    }
+}
+
+fn replay(path: &Path) -> io::Result<Vec<JobSummary>> {
+    let mut order = Vec::new();
+    let mut latest: HashMap<Uuid, JobSummary> = HashMap::new();
+
+    for (line_number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JobSummary>(&line) {
+            Ok(job) => {
+                if !latest.contains_key(&job.job_id) {
+                    order.push(job.job_id);
+                }
+                latest.insert(job.job_id, job);
+            }
+            // A crash in the middle of an append leaves a torn last line
+            Err(e) => tracing::warn!(
+                "Skipping unreadable line {} of {}: {}",
+                line_number + 1,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    Ok(order
+        .into_iter()
+        .filter_map(|job_id| latest.remove(&job_id))
+        .collect())
+}
+
+/// Rewrite the log at `path` to hold only `jobs`, and open it for appending
+fn compact(path: &Path, jobs: &[JobSummary]) -> io::Result<File> {
+    // Write the snapshots to a new file and swap it in
+    let compacted = path.with_extension("log.tmp");
+    {
+        let mut file = File::create(&compacted)?;
+        for job in jobs {
+            writeln!(file, "{}", serde_json::to_string(job)?)?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&compacted, path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+fn input_path(dir: &Path, job_id: Uuid) -> PathBuf {
+    dir.join(INPUTS_DIR).join(format!("{job_id}.json"))
+}
+
+fn proof_path(dir: &Path, job_id: Uuid) -> PathBuf {
+    dir.join(PROOFS_DIR).join(format!("{job_id}.bin"))
+}
+
+fn remove_if_exists(path: &Path) {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            tracing::warn!("Failed to remove {}: {}", path.display(), e)
+        }
+        _ => {}
+    }
+}
+
+/// Write `bytes` to `path` so that a crash leaves either the old file or the full new one
+fn write_durably(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
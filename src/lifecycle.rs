@@ -0,0 +1,268 @@
+//! Whether the server accepts new work, and draining it before shutdown.
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::common::AppState;
+use crate::config::LeftoverPolicy;
+use crate::scheduler::Scheduler;
+
+// How often the drain checks whether running work has finished
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Accepting and running work
+    Running,
+    /// New work is held until the server is resumed, waiting proofs are not started
+    Paused,
+    /// New work is rejected, accepted work runs to completion
+    Draining,
+    /// The process is stopping; new work is rejected and waiting proofs are not started
+    ShuttingDown,
+}
+
+pub struct Lifecycle {
+    mode: watch::Sender<Mode>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Lifecycle {
+    pub fn new(scheduler: Arc<Scheduler>) -> Self {
+        Self {
+            mode: watch::channel(Mode::Running).0,
+            scheduler,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        *self.mode.borrow()
+    }
+
+    /// Hold new execute/prove work until `resume` is called
+    pub fn pause(&self) -> Result<Mode, (StatusCode, String)> {
+        self.transition(Mode::Paused, |mode| {
+            matches!(mode, Mode::Running | Mode::Paused)
+        })
+    }
+
+    /// Leave the paused or draining mode
+    pub fn resume(&self) -> Result<Mode, (StatusCode, String)> {
+        self.transition(Mode::Running, |mode| mode != Mode::ShuttingDown)
+    }
+
+    /// Reject new execute/prove work, but let accepted work finish
+    pub fn drain(&self) -> Result<Mode, (StatusCode, String)> {
+        self.transition(Mode::Draining, |mode| mode != Mode::ShuttingDown)
+    }
+
+    /// Reject new work and stop starting waiting proofs, ahead of a shutdown
+    pub fn begin_shutdown(&self) {
+        self.mode.send_replace(Mode::ShuttingDown);
+        self.scheduler.hold(true);
+    }
+
+    /// Wait until new execute/prove work may start. Returns an error once the
+    /// server is draining, including when it starts draining while paused.
+    pub async fn admit(&self) -> Result<(), (StatusCode, String)> {
+        let mut mode = self.mode.subscribe();
+        loop {
+            let current = *mode.borrow_and_update();
+            match current {
+                Mode::Running => return Ok(()),
+                Mode::Paused => {}
+                Mode::Draining | Mode::ShuttingDown => {
+                    return Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Server is draining and does not accept new work".to_string(),
+                    ));
+                }
+            }
+            // The sender lives as long as `self`, so this never errors
+            let _ = mode.changed().await;
+        }
+    }
+
+    fn transition(
+        &self,
+        to: Mode,
+        allowed_from: impl Fn(Mode) -> bool,
+    ) -> Result<Mode, (StatusCode, String)> {
+        let mut result = Ok(to);
+        self.mode.send_if_modified(|mode| {
+            if !allowed_from(*mode) {
+                result = Err((
+                    StatusCode::CONFLICT,
+                    format!("Cannot switch from {:?} to {:?}", mode, to),
+                ));
+                return false;
+            }
+            let changed = *mode != to;
+            *mode = to;
+            changed
+        });
+        if result.is_ok() {
+            self.scheduler.hold(to == Mode::Paused);
+        }
+        result
+    }
+}
+
+/// Stop taking new work and wait up to `timeout` for running executions and proofs
+/// to finish. Proofs still waiting for a slot are not started.
+///
+/// Whatever has not finished by then is either left in the job log, to be handled
+/// by the recovery policy on the next start, or cancelled. Either way, the requests
+/// dropped when the process exits leave their jobs as they are.
+pub async fn drain(state: &AppState, timeout: Duration, leftovers: LeftoverPolicy) {
+    state.lifecycle.begin_shutdown();
+    state.jobs.detach();
+
+    let deadline = Instant::now() + timeout;
+    while !is_idle(state) {
+        if Instant::now() >= deadline {
+            println!("Drain deadline of {:?} reached", timeout);
+            break;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+
+    let unfinished = state.jobs.unfinished();
+    if unfinished.is_empty() {
+        println!("Drain complete");
+        state.jobs.flush().await;
+        return;
+    }
+    match leftovers {
+        LeftoverPolicy::Persist => println!(
+            "Leaving {} unfinished jobs in the job log for the next start",
+            unfinished.len()
+        ),
+        LeftoverPolicy::Cancel => {
+            println!("Cancelling {} unfinished jobs", unfinished.len());
+            for job in unfinished {
+                state
+                    .jobs
+                    .cancel(job.job_id, "Cancelled by server shutdown".to_string());
+            }
+        }
+    }
+    state.jobs.flush().await;
+}
+
+fn is_idle(state: &AppState) -> bool {
+    state.scheduler.running() == 0 && state.jobs.running() == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ClientID, ProgramID};
+    use crate::config::Config;
+    use crate::jobs::{JobKind, JobStatus};
+
+    fn lifecycle() -> Arc<Lifecycle> {
+        Arc::new(Lifecycle::new(Arc::new(Scheduler::new(1))))
+    }
+
+    #[tokio::test]
+    async fn test_admit_rejects_while_draining() {
+        let lifecycle = lifecycle();
+        assert!(lifecycle.admit().await.is_ok());
+
+        lifecycle.drain().unwrap();
+
+        let (status, _) = lifecycle.admit().await.unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_admit_waits_while_paused() {
+        let lifecycle = lifecycle();
+        lifecycle.pause().unwrap();
+
+        let waiting = {
+            let lifecycle = lifecycle.clone();
+            tokio::spawn(async move { lifecycle.admit().await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        lifecycle.resume().unwrap();
+        assert!(waiting.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_drain_while_paused_rejects_held_work() {
+        let lifecycle = lifecycle();
+        lifecycle.pause().unwrap();
+
+        let waiting = {
+            let lifecycle = lifecycle.clone();
+            tokio::spawn(async move { lifecycle.admit().await })
+        };
+        tokio::task::yield_now().await;
+        lifecycle.drain().unwrap();
+
+        let (status, _) = waiting.await.unwrap().unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_cannot_resume_during_shutdown() {
+        let lifecycle = lifecycle();
+        lifecycle.begin_shutdown();
+
+        let (status, _) = lifecycle.resume().unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(lifecycle.mode(), Mode::ShuttingDown);
+    }
+
+    #[tokio::test]
+    async fn test_drain_cancels_leftovers_after_deadline() {
+        let state = AppState::new(&Config::default());
+        let job = state.jobs.submit(
+            JobKind::Execute,
+            ProgramID("sp1".to_string()),
+            None,
+            ClientID::default(),
+            None,
+            None,
+        );
+
+        drain(&state, Duration::from_millis(10), LeftoverPolicy::Cancel).await;
+
+        assert_eq!(state.lifecycle.mode(), Mode::ShuttingDown);
+        let summary = state.jobs.get(job.job_id()).unwrap();
+        assert_eq!(summary.status, JobStatus::Cancelled);
+        assert_eq!(
+            summary.error.as_deref(),
+            Some("Cancelled by server shutdown")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_keeps_leftovers_for_next_start() {
+        let state = AppState::new(&Config::default());
+        let job = state.jobs.submit(
+            JobKind::Prove,
+            ProgramID("sp1".to_string()),
+            None,
+            ClientID::default(),
+            None,
+            None,
+        );
+
+        drain(&state, Duration::from_millis(10), LeftoverPolicy::Persist).await;
+        // The process exits, dropping the request waiting for a slot
+        let job_id = job.job_id();
+        drop(job);
+
+        let summary = state.jobs.get(job_id).unwrap();
+        assert_eq!(summary.status, JobStatus::Queued);
+    }
+}
@@ -1,28 +1,67 @@
+mod benchmark;
 mod common;
+mod config;
 mod endpoints;
+mod jobs;
+mod lifecycle;
+mod profile;
 mod program;
+mod reload;
+mod scheduler;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "mock"))]
 mod mock_zkvm;
 
+#[cfg(not(any(feature = "sp1", feature = "mock")))]
+compile_error!("Enable at least one zkVM backend: the `sp1` or `mock` feature");
+
 use axum::{
-    Router,
+    Router, middleware,
     routing::{get, post},
 };
+use benchmark::BenchmarkHistory;
 use common::{AppState, ProgramID, zkVMInstance, zkVMVendor};
-use endpoints::{execute_program, get_server_info, prove_program, verify_proof};
-use program::get_sp1_compiled_program;
-use std::{fs, net::SocketAddr, path::PathBuf, sync::Arc};
+use config::{Config, RecoveryPolicy};
+use endpoints::admin;
+use endpoints::benchmark::{get_regressions, list_benchmarks};
+use endpoints::prove::resume_proof;
+use endpoints::{
+    compare_executions, download_program_elf, estimate_proof, execute_program, get_job,
+    get_job_proof, get_program_elf, get_queue, get_readiness, get_server_info, get_status,
+    list_jobs, prove_program, reprioritize_job, run_benchmark, validate_input, verify_proof,
+};
+use jobs::{JobKind, JobStore, JobSummary};
+use reload::ReloadTrigger;
+use std::{fs, net::SocketAddr, time::Duration};
 use tokio::net::TcpListener;
 use tokio::signal;
+use tokio::sync::oneshot;
 use tower_http::trace::TraceLayer;
 
+// How long open connections get to finish once the drain is over
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+// How often retired program versions are checked for being unused
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
 fn app(state: AppState) -> Router {
     Router::new()
         .route("/execute", post(execute_program))
+        .route("/execute/compare", post(compare_executions))
         .route("/prove", post(prove_program))
         .route("/verify", post(verify_proof))
+        .route("/validate-input", post(validate_input))
+        .route("/estimate", post(estimate_proof))
+        .route("/benchmark", post(run_benchmark))
+        .route("/benchmarks", get(list_benchmarks))
+        .route("/benchmarks/regressions", get(get_regressions))
         .route("/info", get(get_server_info))
+        .route("/ready", get(get_readiness))
+        .route("/programs/:program_id/elf", get(get_program_elf))
+        .route("/programs/:program_id/elf/raw", get(download_program_elf))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/:job_id", get(get_job))
+        .route("/jobs/:job_id/proof", get(get_job_proof))
+        .nest("/admin", admin_routes(state.clone()))
         .with_state(state)
         .layer(TraceLayer::new_for_http())
         // 400MB limit to account for the proof size
@@ -30,6 +69,21 @@ fn app(state: AppState) -> Router {
         .layer(axum::extract::DefaultBodyLimit::max(400 * 1024 * 1024))
 }
 
+/// Operator routes, behind the admin token
+fn admin_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/status", get(get_status))
+        .route("/pause", post(admin::pause))
+        .route("/resume", post(admin::resume))
+        .route("/drain", post(admin::drain))
+        .route("/programs", get(admin::get_programs))
+        .route("/programs/reloads", get(admin::get_reloads))
+        .route("/aliases/:alias", post(admin::set_alias))
+        .route("/queue", get(get_queue))
+        .route("/queue/:job_id/priority", post(reprioritize_job))
+        .route_layer(middleware::from_extractor_with_state::<admin::AdminAuth, _>(state))
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
@@ -43,53 +97,228 @@ async fn main() -> anyhow::Result<()> {
         .with_ansi(true)
         .init();
 
-    let app = init_state().await;
+    let config = Config::from_env()?;
+    let state = init_state(&config).await?;
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(state.clone()));
+    tokio::spawn(prune_retired_versions(state.clone()));
 
     let addr: SocketAddr = "0.0.0.0:3000".parse()?;
     println!("Poost listening on {addr}");
 
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
-    Ok(())
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let mut server = tokio::spawn(
+        axum::serve(listener, app(state.clone()))
+            .with_graceful_shutdown(async {
+                let _ = stop_rx.await;
+            })
+            .into_future(),
+    );
+
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        _ = shutdown_signal() => {}
+    }
+
+    // Keep serving status requests while running work finishes
+    lifecycle::drain(&state, config.drain_timeout, config.drain_leftovers).await;
+
+    // Give finished requests a moment to send their responses. Requests still
+    // waiting for a proving slot are abandoned; their jobs stay in the job log.
+    let _ = stop_tx.send(());
+    let code = match tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, server).await {
+        Ok(Ok(Ok(()))) => 0,
+        Ok(Ok(Err(e))) => {
+            tracing::error!("Server failed while shutting down: {}", e);
+            1
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Server task failed: {}", e);
+            1
+        }
+        Err(_) => {
+            println!("Closing connections that are still open");
+            0
+        }
+    };
+    // Dropping the runtime would wait for the proofs still running on blocking threads.
+    // The job log is written synchronously, so nothing is lost by exiting right away.
+    std::process::exit(code)
 }
 
-async fn init_state() -> Router {
+async fn init_state(config: &Config) -> anyhow::Result<AppState> {
     // Create programs directory if it doesn't exist
-    let programs_dir = PathBuf::from("programs");
-    fs::create_dir_all(&programs_dir).expect("Failed to create programs directory");
+    fs::create_dir_all(&config.programs_dir).expect("Failed to create programs directory");
 
-    let state = AppState {
-        programs: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
-    };
+    // Restore the job history, including proofs interrupted by the last shutdown
+    let (jobs, interrupted) = JobStore::open(&config.data_dir, config.job_history_limit)
+        .expect("Failed to open job store");
+    let benchmarks = BenchmarkHistory::open(&config.data_dir, config.regression)
+        .expect("Failed to open benchmark history");
+    let state = AppState::new(config)
+        .with_jobs(jobs)
+        .with_benchmarks(benchmarks);
+
+    if config.admin_token.is_none() {
+        println!("Admin API disabled, set POOST_ADMIN_TOKEN to enable it");
+    }
+    if config.elf_trust.is_enabled() {
+        println!("Only loading allowlisted or signed ELFs");
+    }
+
+    // Keep the program built in for each backend under a fixed program ID, next to
+    // the programs shipped as manifests
+    for &vendor in &config.backends {
+        let (version, instance) = builtin_program(vendor, config)?;
+        state
+            .reloader
+            .add_builtin(ProgramID::from(vendor), version, instance)
+            .await;
+    }
 
-    // Compile the SP1 program at startup
-    println!("Compiling SP1 program...");
-    let sp1_zkvm = get_sp1_compiled_program();
-    println!("SP1 program compiled successfully");
-
-    // Save the compiled zkvm program instance in the app state with a fixed program ID
-    let program_id = ProgramID::from(zkVMVendor::SP1);
-    {
-        let mut programs = state.programs.write().await;
-        programs.insert(
-            program_id.clone(),
-            zkVMInstance::new(zkVMVendor::SP1, Arc::new(sp1_zkvm)),
+    // Register all programs; a broken manifest stops the startup
+    state
+        .reloader
+        .reload(&state.programs, &state.jobs, ReloadTrigger::Startup)
+        .await?;
+    for &vendor in &config.backends {
+        println!(
+            "{} program saved with ID: {:?}",
+            vendor,
+            ProgramID::from(vendor)
         );
     }
 
-    println!("SP1 program saved with ID: {:?}", program_id);
+    if config.watch_programs {
+        reload::watch(
+            state.reloader.clone(),
+            state.programs.clone(),
+            state.jobs.clone(),
+        )?;
+    }
+
+    recover_interrupted_jobs(&state, interrupted, config.recovery_policy);
+
+    Ok(state)
+}
+
+/// The program built into the binary for `vendor`, with its version
+fn builtin_program(vendor: zkVMVendor, config: &Config) -> anyhow::Result<(String, zkVMInstance)> {
+    match vendor {
+        #[cfg(feature = "sp1")]
+        zkVMVendor::SP1 => {
+            use anyhow::Context;
+            use ere_sp1::EreSP1;
+            use program::{elf_digest, load_sp1_elf, sp1_public_values};
+            use std::sync::Arc;
+            use zkvm_interface::ProverResourceType;
 
-    // Build our application with a route
-    let app = app(state);
+            let sp1 = load_sp1_elf(config.sp1_elf.as_deref(), config.sp1_elf_digest.as_deref())?;
+            config
+                .elf_trust
+                .check(&sp1.elf, sp1.signature.as_deref())
+                .with_context(|| format!("Refusing to load the SP1 program {}", sp1.source))?;
 
-    app
+            println!("Setting up SP1 program from {}...", sp1.source);
+            let sp1_zkvm = EreSP1::new(sp1.elf.clone(), ProverResourceType::Cpu);
+            println!("SP1 program set up successfully");
+            Ok((
+                elf_digest(&sp1.elf),
+                zkVMInstance::new(zkVMVendor::SP1, Arc::new(sp1_zkvm))
+                    .with_elf(sp1.elf)
+                    .with_public_values(sp1_public_values),
+            ))
+        }
+        #[cfg(feature = "mock")]
+        zkVMVendor::Mock => Ok((
+            "mock".to_string(),
+            zkVMInstance::new(
+                zkVMVendor::Mock,
+                std::sync::Arc::new(mock_zkvm::MockZkVM::new(config.mock.clone())),
+            )
+            // The mock has no guest to feed, so it takes any bytes
+            .with_input(program::InputKind::Raw)
+            .with_public_values(mock_zkvm::mock_public_values),
+        )),
+        vendor => anyhow::bail!("This build does not include the {} backend", vendor),
+    }
 }
 
+fn recover_interrupted_jobs(
+    state: &AppState,
+    interrupted: Vec<JobSummary>,
+    policy: RecoveryPolicy,
+) {
+    for job in interrupted {
+        match (job.kind, policy) {
+            (JobKind::Prove, RecoveryPolicy::Resume) => {
+                tokio::spawn(resume_proof(state.clone(), job));
+            }
+            _ => {
+                println!(
+                    "Marking job {} interrupted by the restart as failed",
+                    job.job_id
+                );
+                if let Some(handle) = state.jobs.reopen(job.job_id) {
+                    handle.failed("Interrupted by a server restart".to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Reload the programs directory on every SIGHUP
+#[cfg(unix)]
+async fn reload_on_sighup(state: AppState) {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("failed to install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        println!("Reloading programs");
+        reload::log_reload(
+            state
+                .reloader
+                .reload(&state.programs, &state.jobs, ReloadTrigger::Signal)
+                .await,
+        );
+    }
+}
+
+/// Unload the program versions retired by reloads once no job needs them anymore
+async fn prune_retired_versions(state: AppState) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        state
+            .reloader
+            .prune_retired(&state.programs, &state.jobs)
+            .await;
+    }
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM as sent by container orchestrators
 async fn shutdown_signal() {
-    signal::ctrl_c()
-        .await
-        .expect("failed to install CTRL+C handler");
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install CTRL+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
     println!("graceful shutdown");
 }
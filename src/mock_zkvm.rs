@@ -1,33 +1,201 @@
-// A lightweight mock implementation of the zkVM trait that can be used for unit tests.
+// A lightweight mock implementation of the zkVM trait that can be used for unit tests,
+// and as the `mock` backend when built with the `mock` feature.
+//
+// Its cycle count grows with the input size, and its proofs commit to the SHA-256 of the
+// input, so the same input always gets the same proof. Latency and failures can be injected
+// through `MockConfig`. It runs no guest, so its proofs carry no public values unless a test
+// gives it some to commit. A profiled block input gets the regions the guest emits, with the
+// names of `poost_validation::regions`.
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use indexmap::IndexMap;
+use poost_validation::regions;
+use reth_stateless::ClientInput;
+use sha2::{Digest, Sha256};
 use zkvm_interface::{Input, ProgramExecutionReport, ProgramProvingReport, zkVM, zkVMError};
 
-#[derive(Default)]
-pub struct MockZkVM;
+use crate::config::MockConfig;
+
+const PROOF_MAGIC: &[u8] = b"mock_proof";
+
+#[derive(Debug, Default, Clone)]
+pub struct MockZkVM {
+    config: MockConfig,
+    /// What the proofs commit as the public values of the guest
+    public_values: Vec<u8>,
+}
+
+impl MockZkVM {
+    pub fn new(config: MockConfig) -> Self {
+        Self {
+            config,
+            public_values: Vec::new(),
+        }
+    }
+
+    /// Commit `public_values` in every proof, as a guest would
+    #[cfg(test)]
+    pub fn committing(mut self, public_values: Vec<u8>) -> Self {
+        self.public_values = public_values;
+        self
+    }
+
+    fn cycles(&self, inputs: &Input) -> u64 {
+        let size = inputs.bytes().len() as u64;
+        self.config
+            .base_cycles
+            .saturating_add(self.config.cycles_per_byte.saturating_mul(size))
+    }
+
+    /// The regions of a profiled input carrying blocks. With no EVM to run, a region costs the
+    /// cycles of the bytes it reads, and no precompile is called.
+    fn profile_regions(&self, inputs: &Input) -> IndexMap<String, u64> {
+        // The first block, the chain, the number of blocks that follow, those blocks and
+        // whether to profile, see `ProgramInput::to_profiled_input`
+        let chunks: Vec<&[u8]> = inputs.chunked_iter().collect();
+        let Some((profile, chunks)) = chunks.split_last() else {
+            return IndexMap::new();
+        };
+        if chunks.len() < 3 || !bincode::deserialize::<bool>(profile).unwrap_or(false) {
+            return IndexMap::new();
+        }
+        let blocks = std::iter::once(chunks[0]).chain(chunks[3..].iter().copied());
+        let cycles = |bytes: usize| self.config.cycles_per_byte.saturating_mul(bytes as u64);
+
+        let mut profile = IndexMap::new();
+        let mut validation = 0;
+        for block in blocks {
+            let Ok(input) = bincode::deserialize::<ClientInput>(block) else {
+                return IndexMap::new();
+            };
+            let number = input.block.header.number;
+            let path = format!("{}/{}", regions::VALIDATION, regions::block(number));
+            let witness = &input.witness;
+            let mut regions = vec![(
+                regions::WITNESS.to_string(),
+                cycles(
+                    witness
+                        .headers
+                        .iter()
+                        .chain(&witness.codes)
+                        .map(|b| b.len())
+                        .sum(),
+                ),
+            )];
+            for (index, transaction) in input.block.body.transactions.iter().enumerate() {
+                let size = bincode::serialized_size(transaction).unwrap_or_default();
+                regions.push((regions::transaction(number, index), cycles(size as usize)));
+            }
+            regions.push((
+                regions::STATE_ROOT.to_string(),
+                cycles(witness.state.iter().map(|node| node.len()).sum()),
+            ));
+
+            let block_cycles = regions.iter().map(|(_, cycles)| cycles).sum();
+            validation += block_cycles;
+            profile.insert(path.clone(), block_cycles);
+            for (name, cycles) in regions {
+                profile.insert(format!("{path}/{name}"), cycles);
+            }
+        }
+        profile.insert(regions::VALIDATION.to_string(), validation);
+        profile
+    }
+
+    fn maybe_fail(&self) -> Result<(), zkVMError> {
+        if self.config.failure_rate > 0.0 && rand::random::<f64>() < self.config.failure_rate {
+            return Err(zkVMError::Other(Box::new(std::io::Error::other(
+                "injected mock failure",
+            ))));
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for `stateless_validation` in tests, whose block fixtures are consistent but do
+/// not pass the consensus checks of a real chain. Only a block using more gas than its limit
+/// is invalid.
+#[cfg(test)]
+pub fn mock_block_validation(
+    input: &ClientInput,
+    _spec: std::sync::Arc<reth_chainspec::ChainSpec>,
+) -> Result<alloy_primitives::B256, reth_stateless::validation::StatelessValidationError> {
+    let header = &input.block.header;
+    if header.gas_used > header.gas_limit {
+        return Err(
+            reth_stateless::validation::StatelessValidationError::StatelessExecutionFailed(
+                format!(
+                    "block gas used {} exceeds the gas limit {}",
+                    header.gas_used, header.gas_limit
+                ),
+            ),
+        );
+    }
+    Ok(header.hash_slow())
+}
+
+/// The proof of `inputs`: the magic, the input digest, a tag binding the digest to the public
+/// values, and the public values
+pub fn mock_proof(inputs: &Input, public_values: &[u8]) -> Vec<u8> {
+    let digest = Sha256::digest(inputs.bytes());
+    let mut proof = PROOF_MAGIC.to_vec();
+    proof.extend_from_slice(&digest);
+    proof.extend_from_slice(&proof_tag(&digest, public_values));
+    proof.extend_from_slice(public_values);
+    proof
+}
+
+/// The public values committed in a mock proof
+pub fn mock_public_values(proof: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (_, public_values) = split_proof(proof).ok_or_else(|| anyhow::anyhow!("invalid proof"))?;
+    Ok(public_values.to_vec())
+}
+
+/// The input digest and the public values of a well-formed proof
+fn split_proof(proof: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = proof.strip_prefix(PROOF_MAGIC)?;
+    if rest.len() < 64 {
+        return None;
+    }
+    let (digest, rest) = rest.split_at(32);
+    let (tag, public_values) = rest.split_at(32);
+    (proof_tag(digest, public_values) == tag).then_some((digest, public_values))
+}
+
+fn proof_tag(digest: &[u8], public_values: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(PROOF_MAGIC)
+        .chain_update(digest)
+        .chain_update(public_values)
+        .finalize()
+        .into()
+}
 
 impl zkVM for MockZkVM {
-    fn execute(&self, _inputs: &Input) -> Result<ProgramExecutionReport, zkVMError> {
-        // Simulate some computation time to avoid 0-ms durations in unit tests
-        std::thread::sleep(Duration::from_millis(1));
+    fn execute(&self, inputs: &Input) -> Result<ProgramExecutionReport, zkVMError> {
+        std::thread::sleep(self.config.execute_latency);
+        self.maybe_fail()?;
         Ok(ProgramExecutionReport {
-            total_num_cycles: 100,
-            region_cycles: Default::default(),
+            total_num_cycles: self.cycles(inputs),
+            region_cycles: self.profile_regions(inputs),
         })
     }
 
-    fn prove(&self, _inputs: &Input) -> Result<(Vec<u8>, ProgramProvingReport), zkVMError> {
+    fn prove(&self, inputs: &Input) -> Result<(Vec<u8>, ProgramProvingReport), zkVMError> {
+        let start = Instant::now();
+        std::thread::sleep(self.config.prove_latency);
+        self.maybe_fail()?;
         Ok((
-            b"mock_proof".to_vec(),
+            mock_proof(inputs, &self.public_values),
             ProgramProvingReport {
-                proving_time: Duration::from_millis(1),
+                proving_time: start.elapsed().max(Duration::from_millis(1)),
             },
         ))
     }
 
     fn verify(&self, proof: &[u8]) -> Result<(), zkVMError> {
-        if proof == b"mock_proof" {
+        if split_proof(proof).is_some() {
             Ok(())
         } else {
             Err(zkVMError::Other(Box::new(std::io::Error::new(
@@ -37,3 +205,69 @@ impl zkVM for MockZkVM {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(bytes: &[u8]) -> Input {
+        let mut input = Input::new();
+        input.write_slice(bytes);
+        input
+    }
+
+    #[test]
+    fn test_cycles_grow_with_input_size() {
+        let vm = MockZkVM::new(MockConfig {
+            base_cycles: 10,
+            cycles_per_byte: 3,
+            execute_latency: Duration::ZERO,
+            ..MockConfig::default()
+        });
+
+        assert_eq!(vm.execute(&input(b"")).unwrap().total_num_cycles, 10);
+        assert_eq!(vm.execute(&input(b"abcd")).unwrap().total_num_cycles, 22);
+    }
+
+    #[test]
+    fn test_proofs_are_bound_to_the_input() {
+        let vm = MockZkVM::default();
+
+        let (proof, _) = vm.prove(&input(b"a")).unwrap();
+        assert_eq!(proof, vm.prove(&input(b"a")).unwrap().0);
+        assert_ne!(proof, vm.prove(&input(b"b")).unwrap().0);
+        assert!(vm.verify(&proof).is_ok());
+
+        let mut tampered = proof.clone();
+        tampered[PROOF_MAGIC.len()] ^= 1;
+        assert!(vm.verify(&tampered).is_err());
+        assert!(vm.verify(b"mock_proof").is_err());
+        assert_eq!(mock_public_values(&proof).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_public_values() {
+        let vm = MockZkVM::default().committing(b"outcome".to_vec());
+
+        let (proof, _) = vm.prove(&input(b"a")).unwrap();
+        assert!(vm.verify(&proof).is_ok());
+        assert_eq!(mock_public_values(&proof).unwrap(), b"outcome");
+
+        // The public values are bound to the proof
+        let mut tampered = proof.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(vm.verify(&tampered).is_err());
+        assert!(mock_public_values(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_injected_failures() {
+        let vm = MockZkVM::new(MockConfig {
+            failure_rate: 1.0,
+            ..MockConfig::default()
+        });
+
+        assert!(vm.execute(&input(b"a")).is_err());
+        assert!(vm.prove(&input(b"a")).is_err());
+    }
+}
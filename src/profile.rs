@@ -0,0 +1,327 @@
+//! Turning the flat region cycles of an execution into a tree, exporting it for flamegraph
+//! tooling, and comparing the regions of two executions.
+//!
+//! Regions nest by name: `validation/execution` is a child of `validation`. A parent that was
+//! not reported itself gets the sum of its children.
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// Name of the root node, which holds the total cycles of the run
+const ROOT: &str = "total";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RegionNode {
+    pub name: String,
+    /// Cycles of the region, including its children
+    pub total_cycles: u64,
+    /// Cycles of the region outside of its children
+    pub self_cycles: u64,
+    /// Most expensive first
+    pub children: Vec<RegionNode>,
+}
+
+/// Format of a profile export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileFormat {
+    /// Collapsed stacks, one `a;b;c <cycles>` line per region, as read by `flamegraph.pl`
+    /// and `inferno`
+    Collapsed,
+    /// The speedscope file format
+    Speedscope,
+}
+
+/// Node being built, keyed by the name of the child
+#[derive(Default)]
+struct Builder {
+    cycles: Option<u64>,
+    children: IndexMap<String, Builder>,
+}
+
+impl Builder {
+    fn build(self, name: String) -> RegionNode {
+        let mut children: Vec<RegionNode> = self
+            .children
+            .into_iter()
+            .map(|(name, child)| child.build(name))
+            .collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.total_cycles));
+
+        let children_cycles: u64 = children.iter().map(|child| child.total_cycles).sum();
+        let total_cycles = self.cycles.unwrap_or(children_cycles);
+        RegionNode {
+            name,
+            total_cycles,
+            self_cycles: total_cycles.saturating_sub(children_cycles),
+            children,
+        }
+    }
+}
+
+/// Build the region tree of a run of `total_cycles` cycles
+pub fn region_tree(total_cycles: u64, region_cycles: &IndexMap<String, u64>) -> RegionNode {
+    let mut root = Builder {
+        cycles: Some(total_cycles),
+        children: IndexMap::new(),
+    };
+    for (name, &cycles) in region_cycles {
+        let node = name.split('/').fold(&mut root, |node, part| {
+            node.children.entry(part.to_string()).or_default()
+        });
+        node.cycles = Some(cycles);
+    }
+    root.build(ROOT.to_string())
+}
+
+impl RegionNode {
+    /// Visit every node with the names of its ancestors and its own
+    fn walk<'a>(
+        &'a self,
+        stack: &mut Vec<&'a str>,
+        visit: &mut impl FnMut(&[&'a str], &'a RegionNode),
+    ) {
+        stack.push(&self.name);
+        visit(stack, self);
+        for child in &self.children {
+            child.walk(stack, visit);
+        }
+        stack.pop();
+    }
+
+    /// Export the tree, as a JSON string for collapsed stacks
+    pub fn export(&self, format: ProfileFormat) -> serde_json::Value {
+        match format {
+            ProfileFormat::Collapsed => serde_json::Value::String(self.collapsed()),
+            ProfileFormat::Speedscope => self.speedscope(),
+        }
+    }
+
+    fn collapsed(&self) -> String {
+        let mut lines = Vec::new();
+        self.walk(&mut Vec::new(), &mut |stack, node| {
+            if node.self_cycles > 0 {
+                // `;` separates the frames
+                let frames: Vec<String> = stack.iter().map(|name| name.replace(';', ":")).collect();
+                lines.push(format!("{} {}", frames.join(";"), node.self_cycles));
+            }
+        });
+        lines.join("\n")
+    }
+
+    /// A sampled speedscope profile, with one sample per region weighted by its self cycles
+    fn speedscope(&self) -> serde_json::Value {
+        let mut frames: IndexMap<&str, usize> = IndexMap::new();
+        let mut samples = Vec::new();
+        let mut weights = Vec::new();
+        self.walk(&mut Vec::new(), &mut |stack, node| {
+            if node.self_cycles > 0 {
+                let sample: Vec<usize> = stack
+                    .iter()
+                    .map(|name| {
+                        let next = frames.len();
+                        *frames.entry(name).or_insert(next)
+                    })
+                    .collect();
+                samples.push(sample);
+                weights.push(node.self_cycles);
+            }
+        });
+
+        serde_json::json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "exporter": "poost",
+            "shared": {
+                "frames": frames.keys().map(|name| serde_json::json!({ "name": name })).collect::<Vec<_>>(),
+            },
+            "profiles": [{
+                "type": "sampled",
+                "name": "cycles",
+                "unit": "none",
+                "startValue": 0,
+                "endValue": self.total_cycles,
+                "samples": samples,
+                "weights": weights,
+            }],
+        })
+    }
+}
+
+/// How the cycles of a region changed between two runs
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RegionDelta {
+    pub name: String,
+    /// `None` when the baseline run did not report the region
+    pub baseline_cycles: Option<u64>,
+    /// `None` when the candidate run did not report the region
+    pub candidate_cycles: Option<u64>,
+    /// Candidate minus baseline cycles, a missing region counting as 0
+    pub delta_cycles: i64,
+    /// Delta relative to the baseline, `None` when the baseline has no cycles for the region
+    pub change_percent: Option<f64>,
+}
+
+impl RegionDelta {
+    fn new(name: String, baseline: Option<u64>, candidate: Option<u64>) -> Self {
+        let (before, after) = (baseline.unwrap_or(0), candidate.unwrap_or(0));
+        let delta_cycles = after as i64 - before as i64;
+        RegionDelta {
+            name,
+            baseline_cycles: baseline,
+            candidate_cycles: candidate,
+            delta_cycles,
+            change_percent: (before > 0).then(|| delta_cycles as f64 / before as f64 * 100.0),
+        }
+    }
+}
+
+/// The region deltas of two runs
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RegionComparison {
+    /// Delta of the total cycles of the runs
+    pub total: RegionDelta,
+    /// Every region reported by either run, largest absolute delta first
+    pub regions: Vec<RegionDelta>,
+}
+
+/// Compare the regions of a baseline and a candidate run
+pub fn compare_regions(
+    baseline_total: u64,
+    baseline: &IndexMap<String, u64>,
+    candidate_total: u64,
+    candidate: &IndexMap<String, u64>,
+) -> RegionComparison {
+    let names: indexmap::IndexSet<&String> = baseline.keys().chain(candidate.keys()).collect();
+    let mut regions: Vec<RegionDelta> = names
+        .into_iter()
+        .map(|name| {
+            RegionDelta::new(
+                name.clone(),
+                baseline.get(name).copied(),
+                candidate.get(name).copied(),
+            )
+        })
+        .collect();
+    // Stable, so regions with equal deltas keep the order they were reported in
+    regions.sort_by_key(|region| std::cmp::Reverse(region.delta_cycles.unsigned_abs()));
+
+    RegionComparison {
+        total: RegionDelta::new(
+            ROOT.to_string(),
+            Some(baseline_total),
+            Some(candidate_total),
+        ),
+        regions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions() -> IndexMap<String, u64> {
+        [
+            ("read_input", 10),
+            ("validation", 80),
+            ("validation/execution", 50),
+            ("validation/state_root", 20),
+            ("witness/trie", 5),
+        ]
+        .into_iter()
+        .map(|(name, cycles)| (name.to_string(), cycles))
+        .collect()
+    }
+
+    #[test]
+    fn test_region_tree() {
+        let tree = region_tree(100, &regions());
+
+        assert_eq!(tree.name, "total");
+        assert_eq!(tree.total_cycles, 100);
+        assert_eq!(tree.self_cycles, 5);
+        let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["validation", "read_input", "witness"]);
+
+        let validation = &tree.children[0];
+        assert_eq!(validation.self_cycles, 10);
+        assert_eq!(validation.children[0].name, "execution");
+        // `witness` was only reported through its child
+        assert_eq!(tree.children[2].total_cycles, 5);
+        assert_eq!(tree.children[2].self_cycles, 0);
+    }
+
+    #[test]
+    fn test_collapsed_export() {
+        let tree = region_tree(100, &regions());
+
+        assert_eq!(
+            tree.export(ProfileFormat::Collapsed),
+            "total 5\n\
+             total;validation 10\n\
+             total;validation;execution 50\n\
+             total;validation;state_root 20\n\
+             total;read_input 10\n\
+             total;witness;trie 5"
+        );
+    }
+
+    #[test]
+    fn test_speedscope_export() {
+        let export = region_tree(100, &regions()).export(ProfileFormat::Speedscope);
+
+        let frames = export["shared"]["frames"].as_array().unwrap();
+        assert_eq!(frames[0]["name"], "total");
+        let profile = &export["profiles"][0];
+        assert_eq!(profile["endValue"], 100);
+        assert_eq!(profile["samples"][2], serde_json::json!([0, 1, 2]));
+        let weights: u64 = profile["weights"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| w.as_u64().unwrap())
+            .sum();
+        assert_eq!(weights, 100);
+    }
+
+    #[test]
+    fn test_compare_regions() {
+        let candidate: IndexMap<String, u64> = [
+            ("read_input", 10),
+            ("validation", 60),
+            ("validation/execution", 35),
+            ("validation/state_root", 20),
+            ("validation/bloom", 4),
+        ]
+        .into_iter()
+        .map(|(name, cycles)| (name.to_string(), cycles))
+        .collect();
+
+        let comparison = compare_regions(100, &regions(), 80, &candidate);
+        assert_eq!(comparison.total.delta_cycles, -20);
+        assert_eq!(comparison.total.change_percent, Some(-20.0));
+
+        let deltas: Vec<(&str, i64)> = comparison
+            .regions
+            .iter()
+            .map(|region| (region.name.as_str(), region.delta_cycles))
+            .collect();
+        assert_eq!(
+            deltas,
+            [
+                ("validation", -20),
+                ("validation/execution", -15),
+                ("witness/trie", -5),
+                ("validation/bloom", 4),
+                ("read_input", 0),
+                ("validation/state_root", 0),
+            ]
+        );
+        // Regions only one run reported
+        let trie = &comparison.regions[2];
+        assert_eq!(trie.candidate_cycles, None);
+        assert_eq!(trie.change_percent, Some(-100.0));
+        let bloom = &comparison.regions[3];
+        assert_eq!(bloom.baseline_cycles, None);
+        assert_eq!(bloom.change_percent, None);
+    }
+}
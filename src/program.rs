@@ -1,50 +1,487 @@
-use ere_sp1::EreSP1;
-use once_cell::sync::Lazy;
+mod attribution;
+mod chain;
+mod manifest;
+mod native;
+mod outcome;
+#[cfg(feature = "sp1")]
+mod sp1;
+mod trust;
+mod validation;
+
+use anyhow::{Context, bail};
+use object::elf::{PF_R, PF_W, PF_X};
+use object::{
+    Architecture, BinaryFormat, Object, ObjectKind, ObjectSection, ObjectSegment, SegmentFlags,
+};
 use reth_stateless::ClientInput;
-use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
-use zkvm_interface::{Input, ProverResourceType};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use zkvm_interface::Input;
+
+pub use attribution::{CycleAttribution, attribute_cycles};
+pub use chain::Chain;
+pub use manifest::{LoadedProgram, Resource, load_manifests};
+pub use native::{
+    BlockValidator, NativeReport, NativeValidation, reth_validation, validate_natively,
+};
+pub use outcome::ValidationOutcome;
+#[cfg(feature = "sp1")]
+pub use sp1::{load_sp1_elf, sp1_public_values};
+pub use trust::{ElfRejection, TrustPolicy};
+pub use validation::InputError;
+use validation::{validate_block_range, validate_client_input};
+
+/// Shape of the input a program reads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputKind {
+    /// A `ClientInput` for stateless block validation
+    #[default]
+    ClientInput,
+    /// A base64 string, handed to the guest as is
+    Raw,
+    /// A list of base64 strings, handed to the guest as separate reads
+    Chunks,
+}
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-#[serde(transparent)]
-pub struct ProgramInput {
-    pub input: ClientInput,
+impl std::fmt::Display for InputKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputKind::ClientInput => write!(f, "client_input"),
+            InputKind::Raw => write!(f, "raw"),
+            InputKind::Chunks => write!(f, "chunks"),
+        }
+    }
 }
 
-// TODO: change to try_from -- need to modify ere to not return bincode::Error
-impl From<ProgramInput> for Input {
-    fn from(value: ProgramInput) -> Self {
+/// The input of a request, decoded according to the program's `InputKind`
+#[derive(Debug, Clone)]
+pub enum ProgramInput {
+    /// A `ClientInput`, with the chain its block belongs to
+    ClientInput(Box<ClientInput>, Chain),
+    Raw(Vec<u8>),
+    Chunks(Vec<Vec<u8>>),
+    /// At least one `ClientInput`, each block the child of the previous one
+    BlockRange(Vec<ClientInput>, Chain),
+}
+
+/// A range of blocks, as given to a program taking `ClientInput`s
+#[derive(Deserialize)]
+struct BlockRangeInput {
+    blocks: Vec<ClientInput>,
+}
+
+impl ProgramInput {
+    /// Decode the JSON `input` of a request for a program taking `kind`
+    pub fn decode(kind: InputKind, input: &serde_json::Value) -> Result<Self, InputError> {
+        Ok(match kind {
+            // Either a single block, or consecutive blocks to validate and prove together
+            InputKind::ClientInput if input.get("blocks").is_some() => {
+                let BlockRangeInput { blocks } = deserialize(input)?;
+                if blocks.is_empty() {
+                    return Err(InputError::new("input.blocks", "must not be empty"));
+                }
+                ProgramInput::BlockRange(blocks, decode_chain(input)?)
+            }
+            InputKind::ClientInput => {
+                ProgramInput::ClientInput(Box::new(deserialize(input)?), decode_chain(input)?)
+            }
+            InputKind::Raw => {
+                let encoded: String = deserialize(input)?;
+                ProgramInput::Raw(decode_base64(&encoded).map_err(|e| InputError::new("input", e))?)
+            }
+            InputKind::Chunks => {
+                let chunks: Vec<String> = deserialize(input)?;
+                ProgramInput::Chunks(
+                    chunks
+                        .iter()
+                        .enumerate()
+                        .map(|(i, chunk)| {
+                            decode_base64(chunk)
+                                .map_err(|e| InputError::new(format!("input[{i}]"), e))
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+        })
+    }
+
+    /// Pre-flight checks of a decoded input, empty when nothing is wrong
+    pub fn validate(&self) -> Vec<InputError> {
+        match self {
+            ProgramInput::ClientInput(input, chain) => {
+                validate_client_input(input, chain.chain_id(), "input")
+            }
+            ProgramInput::BlockRange(blocks, chain) => {
+                validate_block_range(blocks, chain.chain_id())
+            }
+            ProgramInput::Raw(_) | ProgramInput::Chunks(_) => Vec::new(),
+        }
+    }
+
+    /// The blocks to validate and the chain they belong to, for inputs that carry blocks
+    pub fn blocks(&self) -> Option<(&[ClientInput], &Chain)> {
+        match self {
+            ProgramInput::ClientInput(input, chain) => {
+                Some((std::slice::from_ref(&**input), chain))
+            }
+            ProgramInput::BlockRange(blocks, chain) => Some((blocks, chain)),
+            ProgramInput::Raw(_) | ProgramInput::Chunks(_) => None,
+        }
+    }
+
+    /// Number of the (first) block being validated, for inputs that carry blocks
+    pub fn block_number(&self) -> Option<u64> {
+        let (blocks, _) = self.blocks()?;
+        Some(blocks[0].block.header.number)
+    }
+
+    /// Hex encoded SHA-256 of the input kind and of each read handed to the guest, prefixed
+    /// by its length, so that differently split inputs with the same bytes differ
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.kind().to_string());
+        for chunk in self.to_input().chunked_iter() {
+            hasher.update((chunk.len() as u64).to_le_bytes());
+            hasher.update(chunk);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn kind(&self) -> InputKind {
+        match self {
+            ProgramInput::ClientInput(..) | ProgramInput::BlockRange(..) => InputKind::ClientInput,
+            ProgramInput::Raw(_) => InputKind::Raw,
+            ProgramInput::Chunks(_) => InputKind::Chunks,
+        }
+    }
+
+    pub fn to_input(&self) -> Input {
+        self.write_input(false)
+    }
+
+    /// The input of a run in profile mode, in which the guest names its cycle regions after
+    /// the blocks and transactions they measure. Only inputs carrying blocks have such a mode.
+    pub fn to_profiled_input(&self) -> Input {
+        self.write_input(true)
+    }
+
+    fn write_input(&self, profile: bool) -> Input {
         let mut input = Input::new();
-        input.write(&value.input).unwrap();
+        match self {
+            // TODO: change to try_from -- need to modify ere to not return bincode::Error
+            //
+            // The guest reads the first block, the chain, the number of blocks that follow,
+            // those blocks and whether to profile
+            ProgramInput::ClientInput(..) | ProgramInput::BlockRange(..) => {
+                let (blocks, chain) = self.blocks().expect("input carries blocks");
+                input.write(&blocks[0]).unwrap();
+                chain.write_to(&mut input);
+                input.write(&((blocks.len() - 1) as u32)).unwrap();
+                for block in &blocks[1..] {
+                    input.write(block).unwrap();
+                }
+                input.write(&profile).unwrap();
+            }
+            ProgramInput::Raw(bytes) => input.write_slice(bytes),
+            ProgramInput::Chunks(chunks) => {
+                for chunk in chunks {
+                    input.write_slice(chunk);
+                }
+            }
+        }
         input
     }
 }
 
+/// The `chain` next to the fields of a `ClientInput`, which ignores it
+fn decode_chain(input: &serde_json::Value) -> Result<Chain, InputError> {
+    input
+        .get("chain")
+        .map_or(Ok(Chain::default()), |chain| {
+            Chain::deserialize(chain)
+                .map_err(|e| e.to_string())
+                .and_then(Chain::resolve)
+        })
+        .map_err(|e| InputError::new("input.chain", e))
+}
+
+/// Deserialize `input`, locating errors by their path
+fn deserialize<T: serde::de::DeserializeOwned>(input: &serde_json::Value) -> Result<T, InputError> {
+    serde_path_to_error::deserialize(input).map_err(|e| InputError::at(e.path(), e.inner()))
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    use base64::Engine;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("invalid base64: {e}"))
+}
+
+/// Hex encoded SHA-256 of an ELF, identifying a program build
+pub fn elf_digest(elf: &[u8]) -> String {
+    hex::encode(Sha256::digest(elf))
+}
+
+/// Check that `bytes` is a RISC-V executable, as produced by the guest compilers
+pub fn validate_elf(bytes: &[u8]) -> anyhow::Result<()> {
+    let file = object::File::parse(bytes).context("Not an object file")?;
+    if file.format() != BinaryFormat::Elf {
+        bail!("Expected an ELF file, found {:?}", file.format());
+    }
+    if !matches!(
+        file.architecture(),
+        Architecture::Riscv32 | Architecture::Riscv64
+    ) {
+        bail!("Expected a RISC-V binary, found {:?}", file.architecture());
+    }
+    if file.kind() != ObjectKind::Executable {
+        bail!("Expected an executable, found {:?}", file.kind());
+    }
+    Ok(())
+}
+
+/// Layout of a program ELF, as reported by the inspection endpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ElfInfo {
+    pub size: usize,
+    /// SHA-256 of the ELF, which is also its program version
+    pub digest: String,
+    pub architecture: String,
+    pub entry: u64,
+    pub sections: Vec<ElfSection>,
+    pub segments: Vec<ElfSegment>,
+    pub symbol_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ElfSection {
+    pub name: String,
+    pub kind: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ElfSegment {
+    pub address: u64,
+    pub memory_size: u64,
+    pub file_offset: u64,
+    pub file_size: u64,
+    /// Permissions, like `r-x`
+    pub flags: String,
+}
+
+pub fn inspect_elf(bytes: &[u8]) -> anyhow::Result<ElfInfo> {
+    let file = object::File::parse(bytes).context("Not an object file")?;
+
+    let sections = file
+        .sections()
+        .map(|section| ElfSection {
+            name: section.name().unwrap_or_default().to_string(),
+            kind: format!("{:?}", section.kind()),
+            address: section.address(),
+            size: section.size(),
+        })
+        .collect();
+    let segments = file
+        .segments()
+        .map(|segment| {
+            let (file_offset, file_size) = segment.file_range();
+            let flags = match segment.flags() {
+                SegmentFlags::Elf { p_flags } => [(PF_R, 'r'), (PF_W, 'w'), (PF_X, 'x')]
+                    .iter()
+                    .map(|(flag, c)| if p_flags & flag != 0 { *c } else { '-' })
+                    .collect(),
+                _ => String::new(),
+            };
+            ElfSegment {
+                address: segment.address(),
+                memory_size: segment.size(),
+                file_offset,
+                file_size,
+                flags,
+            }
+        })
+        .collect();
+
+    Ok(ElfInfo {
+        size: bytes.len(),
+        digest: elf_digest(bytes),
+        architecture: format!("{:?}", file.architecture()),
+        entry: file.entry(),
+        sections,
+        segments,
+        symbol_count: file.symbols().count(),
+    })
+}
+
+/// Small ELF that passes `validate_elf`: a RISC-V executable with one loadable
+/// segment holding a `.text` section
 #[cfg(test)]
-impl ProgramInput {
-    /// Test input for the mock unit tests
-    pub fn test_input() -> Self {
-        ProgramInput {
-            input: ClientInput {
-                block: Default::default(),
-                witness: Default::default(),
-            },
+pub fn test_elf() -> Vec<u8> {
+    fn push(elf: &mut Vec<u8>, fields: &[u32]) {
+        for field in fields {
+            elf.extend_from_slice(&field.to_le_bytes());
         }
     }
+    let shstrtab = b"\0.text\0.shstrtab\0";
+
+    // Header: 32-bit little endian RISC-V executable, entry point 0x1000
+    let mut elf = b"\x7fELF\x01\x01\x01".to_vec();
+    elf.resize(16, 0);
+    elf.extend_from_slice(&[2, 0, 0xf3, 0]);
+    push(&mut elf, &[1, 0x1000, 52, 108, 0]);
+    for half in [52u16, 32, 1, 40, 3, 2] {
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+    // Program header: read + execute segment at 0x1000
+    push(&mut elf, &[1, 84, 0x1000, 0x1000, 4, 4, 5, 4]);
+    // Contents: a single `nop`, then the section names
+    push(&mut elf, &[0x13]);
+    elf.extend_from_slice(shstrtab);
+    elf.resize(108, 0);
+    // Section headers: null, .text, .shstrtab
+    push(&mut elf, &[0; 10]);
+    push(&mut elf, &[1, 1, 6, 0x1000, 84, 4, 0, 0, 4, 0]);
+    push(
+        &mut elf,
+        &[7, 3, 0, 0, 88, shstrtab.len() as u32, 0, 0, 1, 0],
+    );
+    elf
+}
+
+/// Valid request input for the mock unit tests, for programs taking a `ClientInput`
+#[cfg(test)]
+pub fn test_input() -> serde_json::Value {
+    serde_json::to_value(validation::test_client_input()).unwrap()
+}
+
+/// Valid request input of a range of blocks: blocks 10 and 11
+#[cfg(test)]
+pub fn test_block_range() -> serde_json::Value {
+    serde_json::json!({ "blocks": validation::test_block_range() })
+}
+
+/// Default location of the detached signature of an ELF
+fn sig_path(elf_path: &Path) -> PathBuf {
+    let mut path = elf_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
 }
 
-#[derive(RustEmbed)]
-#[folder = "programs/sp1/"]
-struct Sp1Assets;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_elf() {
+        assert!(validate_elf(&test_elf()).is_ok());
+        assert!(validate_elf(b"not an elf").is_err());
+
+        // Same header, but for x86
+        let mut elf = test_elf();
+        elf[18..20].copy_from_slice(&3u16.to_le_bytes());
+        let error = validate_elf(&elf).unwrap_err();
+        assert!(error.to_string().contains("RISC-V"));
+    }
+
+    #[test]
+    fn test_inspect_elf() {
+        let elf = test_elf();
+        let info = inspect_elf(&elf).unwrap();
+
+        assert_eq!(info.size, elf.len());
+        assert_eq!(info.digest, elf_digest(&elf));
+        assert_eq!(info.architecture, "Riscv32");
+        assert_eq!(info.entry, 0x1000);
+        let names: Vec<&str> = info.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [".text", ".shstrtab"]);
+        assert_eq!(info.sections[0].size, 4);
+        assert_eq!(info.segments.len(), 1);
+        assert_eq!(info.segments[0].address, 0x1000);
+        assert_eq!(info.segments[0].flags, "r-x");
+        assert_eq!(info.symbol_count, 0);
+    }
+
+    #[test]
+    fn test_decode_input_kinds() {
+        let raw = ProgramInput::decode(InputKind::Raw, &serde_json::json!("AAEC")).unwrap();
+        assert_eq!(raw.to_input().bytes(), &[0, 1, 2]);
+        assert_eq!(raw.block_number(), None);
+
+        let chunks =
+            ProgramInput::decode(InputKind::Chunks, &serde_json::json!(["AA==", "AQI="])).unwrap();
+        assert_eq!(chunks.to_input().chunked_iter().count(), 2);
+        // The same bytes, read differently by the guest
+        assert_ne!(chunks.digest(), raw.digest());
+        let chunk = ProgramInput::decode(InputKind::Chunks, &serde_json::json!(["AAEC"])).unwrap();
+        assert_ne!(chunk.digest(), raw.digest());
+        let split =
+            ProgramInput::decode(InputKind::Chunks, &serde_json::json!(["AAE=", "Ag=="])).unwrap();
+        assert_ne!(split.digest(), chunks.digest());
+
+        let client_input = ProgramInput::decode(InputKind::ClientInput, &test_input()).unwrap();
+        assert_eq!(client_input.block_number(), Some(10));
 
-static SP1_COMPILED_PROGRAM: Lazy<EreSP1> = Lazy::new(|| {
-    // Load the ELF file bytes from embedded assets
-    let elf_bytes = Sp1Assets::get("sp1-program.elf")
-        .expect("Embedded SP1 ELF not found")
-        .data;
-    EreSP1::new(elf_bytes.into_owned(), ProverResourceType::Cpu)
-});
+        let error =
+            ProgramInput::decode(InputKind::Chunks, &serde_json::json!(["AA==", "!"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "input[1]: invalid base64: Invalid byte 33, offset 0."
+        );
+        let error = ProgramInput::decode(InputKind::Raw, &test_input()).unwrap_err();
+        assert_eq!(error.path, "input");
+    }
+
+    #[test]
+    fn test_decode_error_path() {
+        let mut input = test_input();
+        input["witness"]["state"] = serde_json::json!(["0x00", "0xzz"]);
+
+        let error = ProgramInput::decode(InputKind::ClientInput, &input).unwrap_err();
+        assert_eq!(error.to_string(), "input.witness.state[1]: invalid hex");
+    }
+
+    #[test]
+    fn test_decode_chain() {
+        let decode = |input| ProgramInput::decode(InputKind::ClientInput, &input);
+
+        let Ok(ProgramInput::ClientInput(_, chain)) = decode(test_input()) else {
+            panic!("expected a ClientInput");
+        };
+        assert_eq!(chain, Chain::Id(1));
 
-pub fn get_sp1_compiled_program() -> &'static EreSP1 {
-    &*SP1_COMPILED_PROGRAM
+        let mut input = test_input();
+        input["chain"] = serde_json::json!("sepolia");
+        let Ok(ProgramInput::ClientInput(_, chain)) = decode(input.clone()) else {
+            panic!("expected a ClientInput");
+        };
+        assert_eq!(chain.chain_id(), 11_155_111);
+        // The chain is part of what the guest reads
+        assert_ne!(
+            decode(input).unwrap().digest(),
+            decode(test_input()).unwrap().digest()
+        );
+
+        let mut input = test_input();
+        input["chain"] = serde_json::json!("ropsten");
+        let error = decode(input).unwrap_err();
+        assert_eq!(error.to_string(), "input.chain: unknown chain \"ropsten\"");
+    }
+
+    #[test]
+    fn test_decode_block_range() {
+        let range = ProgramInput::decode(InputKind::ClientInput, &test_block_range()).unwrap();
+        assert!(matches!(&range, ProgramInput::BlockRange(blocks, _) if blocks.len() == 2));
+        assert_eq!(range.block_number(), Some(10));
+        assert!(range.validate().is_empty());
+
+        let error =
+            ProgramInput::decode(InputKind::ClientInput, &serde_json::json!({"blocks": []}))
+                .unwrap_err();
+        assert_eq!(error.to_string(), "input.blocks: must not be empty");
+    }
 }
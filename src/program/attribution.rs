@@ -0,0 +1,136 @@
+//! Mapping the cycle regions of a profiled guest run back to the blocks and transactions
+//! of its input.
+//!
+//! A guest run in profile mode names its regions after what they measure, below the path of
+//! their parent region, with the names of `poost_validation::regions`:
+//!
+//! - `block:{number}` for the validation of a block
+//! - `tx:{number}:{index}` for the execution of a transaction of a block
+//! - `precompile:{class}` for the calls to a class of precompiles, like `precompile:ecrecover`
+//! - `witness` for checking the ancestor headers and building the tries from the witness
+//! - `state_root` for computing the post-state root
+
+use alloy_primitives::B256;
+use indexmap::IndexMap;
+use poost_validation::regions::Region;
+use reth_primitives_traits::SignedTransaction;
+use reth_stateless::ClientInput;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TransactionCycles {
+    pub block_number: u64,
+    pub index: usize,
+    /// `None` when the input has no transaction at `index`
+    pub hash: Option<B256>,
+    pub cycles: u64,
+}
+
+/// Cycles of a profiled run, by what they were spent on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CycleAttribution {
+    /// Cycles per block number
+    pub blocks: IndexMap<u64, u64>,
+    /// Most expensive first
+    pub transactions: Vec<TransactionCycles>,
+    /// Cycles per precompile, most expensive first
+    pub precompiles: IndexMap<String, u64>,
+    /// Cycles spent on the witnesses of all blocks
+    pub witness: Option<u64>,
+    /// Cycles spent on the state roots of all blocks
+    pub state_root: Option<u64>,
+}
+
+/// Attribute the profile regions of `region_cycles` to `blocks`, or `None` when the run has
+/// no profile regions
+pub fn attribute_cycles(
+    region_cycles: &IndexMap<String, u64>,
+    blocks: &[ClientInput],
+) -> Option<CycleAttribution> {
+    let mut attribution = CycleAttribution::default();
+    let mut profiled = false;
+
+    for (name, &cycles) in region_cycles {
+        match Region::parse(name) {
+            Some(Region::Block(number)) => {
+                attribution.blocks.insert(number, cycles);
+            }
+            Some(Region::Transaction {
+                block_number,
+                index,
+            }) => {
+                let hash = blocks
+                    .iter()
+                    .find(|input| input.block.header.number == block_number)
+                    .and_then(|input| input.block.body.transactions.get(index))
+                    .map(|transaction| *transaction.tx_hash());
+                attribution.transactions.push(TransactionCycles {
+                    block_number,
+                    index,
+                    hash,
+                    cycles,
+                });
+            }
+            // A precompile class shows up once per transaction calling it
+            Some(Region::Precompile(class)) => {
+                *attribution
+                    .precompiles
+                    .entry(class.to_string())
+                    .or_default() += cycles
+            }
+            Some(Region::Witness) => {
+                *attribution.witness.get_or_insert(0) += cycles;
+            }
+            Some(Region::StateRoot) => {
+                *attribution.state_root.get_or_insert(0) += cycles;
+            }
+            None => continue,
+        }
+        profiled = true;
+    }
+
+    attribution
+        .transactions
+        .sort_by_key(|transaction| std::cmp::Reverse(transaction.cycles));
+    attribution.precompiles.sort_by(|_, a, _, b| b.cmp(a));
+    profiled.then_some(attribution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::validation::test_block_range;
+    use crate::program::{Chain, ProgramInput};
+    use zkvm_interface::zkVM;
+
+    #[test]
+    fn test_attribute_profiled_run() {
+        let mut blocks = test_block_range();
+        blocks[1].block.body.transactions.push(Default::default());
+        let input = ProgramInput::BlockRange(blocks.clone(), Chain::default());
+        let vm = MockZkVM::default();
+
+        let report = vm.execute(&input.to_profiled_input()).unwrap();
+        let attribution = attribute_cycles(&report.region_cycles, &blocks).unwrap();
+
+        assert_eq!(attribution.blocks.keys().collect::<Vec<_>>(), [&10, &11]);
+        let transactions: Vec<_> = attribution
+            .transactions
+            .iter()
+            .map(|tx| (tx.block_number, tx.index, tx.hash))
+            .collect();
+        let hash = *blocks[1].block.body.transactions[0].tx_hash();
+        assert_eq!(transactions, vec![(11, 0, Some(hash))]);
+        // A block costs what its witness, transactions and state root cost
+        let parts = attribution.witness.unwrap()
+            + attribution.state_root.unwrap()
+            + attribution.transactions[0].cycles;
+        assert_eq!(attribution.blocks.values().sum::<u64>(), parts);
+        assert!(attribution.precompiles.is_empty());
+
+        // Without profile mode the guest emits no attributable regions
+        let report = vm.execute(&input.to_input()).unwrap();
+        assert_eq!(attribute_cycles(&report.region_cycles, &blocks), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Selecting the chain spec a block is validated against.
+
+use alloy_genesis::Genesis;
+use reth_chainspec::{ChainSpec, HOLESKY, HOODI, MAINNET, SEPOLIA};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use zkvm_interface::Input;
+
+use super::validation::InputError;
+
+/// Chains whose spec is built into reth, by name and chain id
+const KNOWN_CHAINS: [(&str, u64); 4] = [
+    ("mainnet", 1),
+    ("sepolia", 11_155_111),
+    ("holesky", 17_000),
+    ("hoodi", 560_048),
+];
+
+/// The chain of a block, given by the optional `chain` field of a `ClientInput` request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Chain {
+    /// Chain id of a chain built into reth, like `11155111`
+    Id(u64),
+    /// Name of a chain built into reth, like `"sepolia"`
+    Name(String),
+    /// Genesis of any other chain, like a devnet
+    Genesis { genesis: Box<Genesis> },
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain::Id(1)
+    }
+}
+
+/// What the guest reads after the `ClientInput`, mirrors `ChainInput` in `programs/sp1/src/main.rs`
+#[derive(Serialize)]
+enum GuestChain {
+    Id(u64),
+    /// The genesis as JSON, since it does not round-trip through bincode
+    Genesis(String),
+}
+
+impl Chain {
+    /// Resolve a name to its chain id, and check that a chain id is built in
+    pub fn resolve(self) -> Result<Self, String> {
+        match self {
+            Chain::Id(id) if !KNOWN_CHAINS.iter().any(|(_, known)| *known == id) => Err(format!(
+                "unknown chain id {id}, pass the genesis of the chain instead"
+            )),
+            Chain::Name(name) => known_chain_id(&name)
+                .map(Chain::Id)
+                .ok_or_else(|| format!("unknown chain {name:?}")),
+            chain => Ok(chain),
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Chain::Id(id) => *id,
+            Chain::Name(name) => known_chain_id(name).unwrap_or_default(),
+            Chain::Genesis { genesis } => genesis.config.chain_id,
+        }
+    }
+
+    /// The spec to validate blocks of this chain with, an error for a chain not built into
+    /// reth, which `resolve` rejects
+    pub fn spec(&self) -> Result<Arc<ChainSpec>, InputError> {
+        if let Chain::Genesis { genesis } = self {
+            return Ok(Arc::new(ChainSpec::from((**genesis).clone())));
+        }
+        match self.chain_id() {
+            1 => Ok(MAINNET.clone()),
+            11_155_111 => Ok(SEPOLIA.clone()),
+            17_000 => Ok(HOLESKY.clone()),
+            560_048 => Ok(HOODI.clone()),
+            _ => Err(InputError::new(
+                "input.chain",
+                format!("no chain spec for {self:?}, pass the genesis of the chain instead"),
+            )),
+        }
+    }
+
+    /// Append the chain to the input of a guest
+    pub fn write_to(&self, input: &mut Input) {
+        let chain = match self {
+            Chain::Genesis { genesis } => {
+                GuestChain::Genesis(serde_json::to_string(genesis).expect("genesis serializes"))
+            }
+            chain => GuestChain::Id(chain.chain_id()),
+        };
+        input.write(&chain).unwrap();
+    }
+}
+
+fn known_chain_id(name: &str) -> Option<u64> {
+    KNOWN_CHAINS
+        .iter()
+        .find(|(known, _)| name.eq_ignore_ascii_case(known))
+        .map(|(_, id)| *id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(value: serde_json::Value) -> Result<Chain, String> {
+        serde_json::from_value::<Chain>(value).unwrap().resolve()
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            chain(serde_json::json!("Sepolia")),
+            Ok(Chain::Id(11_155_111))
+        );
+        assert_eq!(chain(serde_json::json!(560_048)), Ok(Chain::Id(560_048)));
+        assert!(chain(serde_json::json!("ropsten")).is_err());
+        assert!(
+            chain(serde_json::json!(1337))
+                .unwrap_err()
+                .contains("genesis")
+        );
+
+        let devnet = chain(serde_json::json!({"genesis": {"config": {"chainId": 1337}}})).unwrap();
+        assert_eq!(devnet.chain_id(), 1337);
+        assert!(Arc::ptr_eq(&Chain::Id(17_000).spec().unwrap(), &HOLESKY));
+        assert!(Chain::Name("mainnet".to_string()).spec().is_ok());
+
+        // Never validated against another chain's rules
+        assert_eq!(Chain::Id(1337).spec().unwrap_err().path, "input.chain");
+        assert!(Chain::Name("ropsten".to_string()).spec().is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! Programs shipped as files instead of being built into the binary.
+//!
+//! Every `*.toml` file in the programs directory describes one version of a guest:
+//!
+//! ```toml
+//! name = "stateless-v2"
+//! vendor = "sp1"
+//! elf = "stateless-v2.elf"        # relative to the manifest
+//! resource = "cpu"                # or "gpu", defaults to "cpu"
+//! input = "client_input"          # or "raw" or "chunks", defaults to "client_input"
+//! aliases = ["stateless-mainnet"] # further names pointing at this version
+//! signature = "stateless-v2.sig"  # detached ed25519 signature, defaults to the ELF path + `.sig`
+//! ```
+
+use anyhow::{Context, bail};
+#[cfg(feature = "sp1")]
+use ere_sp1::EreSP1;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(any(feature = "sp1", feature = "mock"))]
+use std::sync::Arc;
+use zkvm_interface::ProverResourceType;
+
+use super::{InputKind, TrustPolicy, elf_digest, sig_path, validate_elf};
+use crate::common::{ProgramID, zkVMInstance, zkVMVendor};
+use crate::config::MockConfig;
+#[cfg(feature = "mock")]
+use crate::mock_zkvm::MockZkVM;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+impl From<Resource> for ProverResourceType {
+    fn from(value: Resource) -> Self {
+        match value {
+            Resource::Cpu => ProverResourceType::Cpu,
+            Resource::Gpu => ProverResourceType::Gpu,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    name: String,
+    vendor: zkVMVendor,
+    elf: PathBuf,
+    #[serde(default)]
+    resource: Resource,
+    #[serde(default)]
+    input: InputKind,
+    #[serde(default)]
+    aliases: Vec<String>,
+    signature: Option<PathBuf>,
+}
+
+/// A program read from a manifest, with its ELF already validated
+#[derive(Debug, Clone)]
+pub struct LoadedProgram {
+    pub program_id: ProgramID,
+    pub vendor: zkVMVendor,
+    pub resource: Resource,
+    pub input: InputKind,
+    /// Names pointing at this version by default, besides `program_id`
+    pub aliases: Vec<ProgramID>,
+    pub manifest_path: PathBuf,
+    pub elf: Vec<u8>,
+    /// SHA-256 of `elf`
+    pub digest: String,
+}
+
+impl LoadedProgram {
+    /// Set up the zkVM for this program
+    #[cfg_attr(not(feature = "mock"), allow(unused_variables))]
+    pub fn instantiate(&self, mock: &MockConfig) -> anyhow::Result<zkVMInstance> {
+        match self.vendor {
+            #[cfg(feature = "sp1")]
+            zkVMVendor::SP1 => Ok(zkVMInstance::new(
+                zkVMVendor::SP1,
+                Arc::new(EreSP1::new(self.elf.clone(), self.resource.into())),
+            )
+            .with_elf(self.elf.clone())
+            .with_input(self.input)
+            .with_public_values(super::sp1_public_values)),
+            #[cfg(feature = "mock")]
+            zkVMVendor::Mock => Ok(zkVMInstance::new(
+                zkVMVendor::Mock,
+                Arc::new(MockZkVM::new(mock.clone())),
+            )
+            .with_elf(self.elf.clone())
+            .with_input(self.input)
+            .with_public_values(crate::mock_zkvm::mock_public_values)),
+            vendor => bail!(
+                "Program {} uses {}, which this build does not support",
+                self.program_id.0,
+                vendor
+            ),
+        }
+    }
+}
+
+/// Read every manifest in `dir`, in file name order.
+///
+/// Fails on the first manifest that cannot be parsed, points at a missing,
+/// invalid or untrusted ELF, reuses a name or alias of another manifest, or has the same ELF
+/// as another manifest.
+pub fn load_manifests(dir: &Path, trust: &TrustPolicy) -> anyhow::Result<Vec<LoadedProgram>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut names = HashSet::new();
+    let mut digests = HashSet::new();
+    let mut programs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let program = load_manifest(&path, trust)
+            .with_context(|| format!("Invalid program manifest {}", path.display()))?;
+        for name in std::iter::once(&program.program_id).chain(&program.aliases) {
+            if !names.insert(name.clone()) {
+                bail!(
+                    "Program {} in {} is already defined by another manifest",
+                    name.0,
+                    path.display()
+                );
+            }
+        }
+        if !digests.insert(program.digest.clone()) {
+            bail!(
+                "The ELF of {} is already registered by another manifest",
+                path.display()
+            );
+        }
+        programs.push(program);
+    }
+    Ok(programs)
+}
+
+fn load_manifest(path: &Path, trust: &TrustPolicy) -> anyhow::Result<LoadedProgram> {
+    let manifest: Manifest = toml::from_str(&fs::read_to_string(path)?)?;
+    if manifest.name.trim().is_empty() || manifest.aliases.iter().any(|a| a.trim().is_empty()) {
+        bail!("names must not be empty");
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let elf_path = dir.join(&manifest.elf);
+    let elf = fs::read(&elf_path)
+        .with_context(|| format!("Failed to read ELF {}", elf_path.display()))?;
+    validate_elf(&elf).with_context(|| format!("Invalid ELF {}", elf_path.display()))?;
+
+    let signature = match &manifest.signature {
+        Some(signature) => Some(
+            fs::read(dir.join(signature))
+                .with_context(|| format!("Failed to read signature {}", signature.display()))?,
+        ),
+        // The default signature file is optional
+        None => fs::read(sig_path(&elf_path)).ok(),
+    };
+    trust.check(&elf, signature.as_deref())?;
+
+    Ok(LoadedProgram {
+        program_id: ProgramID(manifest.name),
+        vendor: manifest.vendor,
+        resource: manifest.resource,
+        input: manifest.input,
+        aliases: manifest.aliases.into_iter().map(ProgramID).collect(),
+        manifest_path: path.to_path_buf(),
+        digest: elf_digest(&elf),
+        elf,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::test_elf;
+
+    // Every program gets a distinct ELF, padded with its name
+    fn write_program(dir: &Path, name: &str, manifest: &str) {
+        let mut elf = test_elf();
+        elf.extend_from_slice(name.as_bytes());
+        fs::write(dir.join(format!("{name}.elf")), elf).unwrap();
+        fs::write(dir.join(format!("{name}.toml")), manifest).unwrap();
+    }
+
+    #[test]
+    fn test_load_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "b",
+            "name = \"b\"\nvendor = \"sp1\"\nelf = \"b.elf\"\nresource = \"gpu\"\n",
+        );
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"a.elf\"\n",
+        );
+        fs::write(dir.path().join("README.md"), "not a manifest").unwrap();
+
+        let programs = load_manifests(dir.path(), &TrustPolicy::default()).unwrap();
+
+        assert_eq!(programs.len(), 2);
+        assert_eq!(programs[0].program_id, ProgramID("a".to_string()));
+        assert_eq!(programs[0].resource, Resource::Cpu);
+        assert_eq!(programs[0].input, InputKind::ClientInput);
+        assert_eq!(programs[1].program_id, ProgramID("b".to_string()));
+        assert_eq!(programs[1].resource, Resource::Gpu);
+        assert!(programs[1].elf.starts_with(&test_elf()));
+    }
+
+    #[test]
+    fn test_missing_elf_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.toml"),
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"missing.elf\"\n",
+        )
+        .unwrap();
+
+        let error = load_manifests(dir.path(), &TrustPolicy::default()).unwrap_err();
+        assert!(format!("{error:#}").contains("Failed to read ELF"));
+    }
+
+    #[test]
+    fn test_invalid_elf_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.elf"), b"#!/bin/sh\n").unwrap();
+        fs::write(
+            dir.path().join("a.toml"),
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"a.elf\"\n",
+        )
+        .unwrap();
+
+        let error = load_manifests(dir.path(), &TrustPolicy::default()).unwrap_err();
+        assert!(format!("{error:#}").contains("Invalid ELF"));
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"a.elf\"\nresources = \"gpu\"\n",
+        );
+
+        assert!(load_manifests(dir.path(), &TrustPolicy::default()).is_err());
+    }
+
+    #[test]
+    fn test_aliases_and_duplicate_elfs() {
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"a.elf\"\naliases = [\"main\"]\n",
+        );
+
+        let programs = load_manifests(dir.path(), &TrustPolicy::default()).unwrap();
+        assert_eq!(programs[0].aliases, vec![ProgramID("main".to_string())]);
+
+        // Same ELF under another name
+        fs::write(
+            dir.path().join("b.toml"),
+            "name = \"b\"\nvendor = \"sp1\"\nelf = \"a.elf\"\n",
+        )
+        .unwrap();
+        let error = load_manifests(dir.path(), &TrustPolicy::default()).unwrap_err();
+        assert!(error.to_string().contains("already registered"));
+    }
+
+    #[test]
+    fn test_duplicate_names_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"x\"\nvendor = \"sp1\"\nelf = \"a.elf\"\n",
+        );
+        write_program(
+            dir.path(),
+            "b",
+            "name = \"x\"\nvendor = \"sp1\"\nelf = \"b.elf\"\n",
+        );
+
+        let error = load_manifests(dir.path(), &TrustPolicy::default()).unwrap_err();
+        assert!(error.to_string().contains("already defined"));
+    }
+
+    #[test]
+    fn test_untrusted_elf_is_rejected() {
+        use crate::program::ElfRejection;
+        use crate::program::trust::RejectionReason;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"a\"\nvendor = \"sp1\"\nelf = \"a.elf\"\n",
+        );
+        let trust = TrustPolicy::new(&["00".repeat(32)], &[]).unwrap();
+
+        let error = load_manifests(dir.path(), &trust).unwrap_err();
+        let rejection = error.downcast_ref::<ElfRejection>().unwrap();
+        assert_eq!(rejection.reason, RejectionReason::NotAllowlisted);
+    }
+
+    #[test]
+    fn test_backend_missing_from_build_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_program(
+            dir.path(),
+            "a",
+            "name = \"a\"\nvendor = \"risc0\"\nelf = \"a.elf\"\n",
+        );
+
+        let programs = load_manifests(dir.path(), &TrustPolicy::default()).unwrap();
+        let error = programs[0]
+            .instantiate(&MockConfig::default())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("does not support"));
+    }
+}
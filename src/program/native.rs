@@ -0,0 +1,167 @@
+//! Running the stateless validation of blocks on the host, next to the guest.
+//!
+//! The guest runs the same `stateless_validation` and the continuity checks of
+//! `poost-validation`, so the host predicts the outcome the guest commits, can compare it with
+//! the committed one, and can reject an invalid block in milliseconds instead of after a full
+//! execution.
+
+use alloy_primitives::B256;
+use poost_validation::{BlockError, check_continuity};
+use reth_chainspec::ChainSpec;
+use reth_stateless::ClientInput;
+use reth_stateless::validation::{StatelessValidationError, stateless_validation};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::chain::Chain;
+use super::outcome::ValidationOutcome;
+use super::validation::InputError;
+
+/// Statistics of a native validation
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NativeReport {
+    pub block_count: usize,
+    pub gas_used: u64,
+    pub transaction_count: usize,
+    pub validation_time_milliseconds: u128,
+}
+
+/// The outcome of a native validation with its statistics, reported next to the outcome the
+/// guest commits
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NativeValidation {
+    pub outcome: ValidationOutcome,
+    #[serde(flatten)]
+    pub report: NativeReport,
+}
+
+/// Validates one block against its witness, returning its hash
+pub type BlockValidator =
+    fn(&ClientInput, Arc<ChainSpec>) -> Result<B256, StatelessValidationError>;
+
+/// The `stateless_validation` of reth, which the guest runs
+pub fn reth_validation(
+    input: &ClientInput,
+    spec: Arc<ChainSpec>,
+) -> Result<B256, StatelessValidationError> {
+    stateless_validation(input.block.clone(), input.witness.clone(), spec)
+}
+
+/// Validate consecutive blocks against their witnesses with `validate`, under the rules of
+/// `chain`, stopping at the first invalid block. Fails only for a chain without a spec.
+pub fn validate_natively(
+    blocks: &[ClientInput],
+    chain: &Chain,
+    validate: BlockValidator,
+) -> Result<(ValidationOutcome, NativeReport), InputError> {
+    let start = Instant::now();
+    let spec = chain.spec()?;
+
+    let result = (|| {
+        let mut block_hash = B256::ZERO;
+        for (i, input) in blocks.iter().enumerate() {
+            let number = input.block.header.number;
+            if i > 0 {
+                check_continuity(&blocks[i - 1].block.header, input)
+                    .map_err(|e| (number, BlockError::from(e)))?;
+            }
+            block_hash =
+                validate(input, spec.clone()).map_err(|e| (number, BlockError::from(e)))?;
+        }
+        Ok(block_hash)
+    })();
+
+    let (first, parent_hash) = (
+        blocks[0].block.header.number,
+        blocks[0].block.header.parent_hash,
+    );
+    let outcome = match result {
+        Ok(block_hash) => {
+            ValidationOutcome::valid(chain.chain_id(), first, parent_hash, block_hash)
+        }
+        Err((number, e)) => {
+            ValidationOutcome::invalid(chain.chain_id(), first, parent_hash, number, &e)
+        }
+    };
+    let report = NativeReport {
+        block_count: blocks.len(),
+        gas_used: blocks.iter().map(|input| input.block.header.gas_used).sum(),
+        transaction_count: blocks
+            .iter()
+            .map(|input| input.block.body.transactions.len())
+            .sum(),
+        validation_time_milliseconds: start.elapsed().as_millis(),
+    };
+    Ok((outcome, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_zkvm::mock_block_validation;
+    use crate::program::validation::{self, test_client_input};
+
+    #[test]
+    fn test_valid_block() {
+        let input = test_client_input();
+        let (outcome, report) = validate_natively(
+            std::slice::from_ref(&input),
+            &Chain::default(),
+            mock_block_validation,
+        )
+        .unwrap();
+
+        assert!(outcome.valid);
+        assert_eq!(outcome.chain_id, 1);
+        assert_eq!(outcome.parent_hash, input.block.header.parent_hash);
+        assert_eq!(outcome.block_hash, Some(input.block.header.hash_slow()));
+        assert_eq!(report.gas_used, 0);
+        assert_eq!(report.transaction_count, 0);
+    }
+
+    #[test]
+    fn test_invalid_block() {
+        let mut input = test_client_input();
+        input.block.header.gas_used = input.block.header.gas_limit + 1;
+
+        let (outcome, _) =
+            validate_natively(&[input], &Chain::default(), mock_block_validation).unwrap();
+        assert!(!outcome.valid);
+        assert_eq!(outcome.block_hash, None);
+        assert!(outcome.reason.unwrap().message.contains("gas"));
+    }
+
+    #[test]
+    fn test_block_range() {
+        let blocks = validation::test_block_range();
+        let (outcome, report) =
+            validate_natively(&blocks, &Chain::default(), mock_block_validation).unwrap();
+
+        assert!(outcome.valid);
+        assert_eq!(outcome.parent_hash, blocks[0].block.header.parent_hash);
+        assert_eq!(outcome.block_hash, Some(blocks[1].block.header.hash_slow()));
+        assert_eq!(report.block_count, 2);
+    }
+
+    #[test]
+    fn test_broken_block_range() {
+        let mut blocks = validation::test_block_range();
+        blocks[1].block.header.parent_hash = B256::ZERO;
+
+        let (outcome, _) =
+            validate_natively(&blocks, &Chain::default(), mock_block_validation).unwrap();
+        let reason = outcome.reason.unwrap();
+        assert_eq!(reason.block_number, 11);
+        assert_eq!(reason.code, "parent_hash_mismatch");
+
+        // The first block of the range changed its state root after the witness was taken
+        let mut blocks = validation::test_block_range();
+        blocks[0].block.header.state_root = B256::repeat_byte(1);
+        blocks[1].block.header.parent_hash = blocks[0].block.header.hash_slow();
+
+        let (outcome, _) =
+            validate_natively(&blocks, &Chain::default(), mock_block_validation).unwrap();
+        assert_eq!(outcome.reason.unwrap().code, "state_root_mismatch");
+    }
+}
@@ -0,0 +1,122 @@
+//! The outcome of validating a block, as committed by the guest.
+//!
+//! `programs/sp1/src/main.rs` commits the same struct as its public values, so the two must
+//! change together.
+
+use alloy_primitives::B256;
+use poost_validation::ReasonCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationOutcome {
+    /// Chain id of the chain spec the blocks were validated against
+    pub chain_id: u64,
+    pub valid: bool,
+    /// Number of the first block
+    pub first_block_number: u64,
+    /// Parent hash of the first block
+    pub parent_hash: B256,
+    /// Hash of the last block, when every block is valid
+    pub block_hash: Option<B256>,
+    /// Why a block is invalid
+    pub reason: Option<InvalidReason>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InvalidReason {
+    /// Number of the first invalid block
+    pub block_number: u64,
+    /// Name of the validation error, like `post_state_root_mismatch`
+    pub code: String,
+    pub message: String,
+}
+
+impl ValidationOutcome {
+    /// Decode the outcome a guest committed as its public values, with bincode like
+    /// `sp1_zkvm::io::commit`
+    pub fn from_public_values(public_values: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(public_values)
+    }
+
+    pub fn valid(
+        chain_id: u64,
+        first_block_number: u64,
+        parent_hash: B256,
+        block_hash: B256,
+    ) -> Self {
+        Self {
+            chain_id,
+            valid: true,
+            first_block_number,
+            parent_hash,
+            block_hash: Some(block_hash),
+            reason: None,
+        }
+    }
+
+    /// Outcome of a range whose block `block_number` failed with `error`
+    pub fn invalid(
+        chain_id: u64,
+        first_block_number: u64,
+        parent_hash: B256,
+        block_number: u64,
+        error: &(impl ReasonCode + fmt::Display + ?Sized),
+    ) -> Self {
+        Self {
+            chain_id,
+            valid: false,
+            first_block_number,
+            parent_hash,
+            block_hash: None,
+            reason: Some(InvalidReason {
+                block_number,
+                code: error.code().to_string(),
+                message: error.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reth_stateless::validation::StatelessValidationError;
+
+    fn post_state_root_mismatch() -> StatelessValidationError {
+        StatelessValidationError::PostStateRootMismatch {
+            got: B256::ZERO,
+            expected: B256::repeat_byte(1),
+        }
+    }
+
+    #[test]
+    fn test_from_public_values() {
+        let outcome =
+            ValidationOutcome::invalid(1, 10, B256::ZERO, 10, &post_state_root_mismatch());
+        let committed = bincode::serialize(&outcome).unwrap();
+        assert_eq!(
+            ValidationOutcome::from_public_values(&committed).unwrap(),
+            outcome
+        );
+        assert!(ValidationOutcome::from_public_values(b"").is_err());
+    }
+
+    #[test]
+    fn test_invalid_outcome() {
+        let error = post_state_root_mismatch();
+        let outcome = ValidationOutcome::invalid(1, 10, B256::ZERO, 10, &error);
+
+        assert!(!outcome.valid);
+        assert_eq!(outcome.block_hash, None);
+        assert_eq!(
+            outcome.reason,
+            Some(InvalidReason {
+                block_number: 10,
+                code: "post_state_root_mismatch".to_string(),
+                message: error.to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! The built-in SP1 guest, either compiled by `build.rs` and embedded in the binary or
+//! loaded from a prebuilt ELF at startup.
+
+use anyhow::{Context, bail};
+use rust_embed::RustEmbed;
+use sp1_sdk::SP1ProofWithPublicValues;
+use std::fs;
+use std::path::Path;
+
+use super::{elf_digest, sig_path, validate_elf};
+
+/// File name of the guest ELF, both in the embedded assets and in prebuilt directories
+const SP1_ELF_NAME: &str = "sp1-program.elf";
+
+#[derive(RustEmbed)]
+#[folder = "programs/sp1/"]
+struct Sp1Assets;
+
+/// An SP1 guest ELF, with its detached signature when one was found next to it
+#[derive(Debug)]
+pub struct Sp1Elf {
+    pub elf: Vec<u8>,
+    pub signature: Option<Vec<u8>>,
+    /// Where the ELF was read from, for logging
+    pub source: String,
+}
+
+/// ELF of the SP1 program embedded in the binary, unless `build.rs` skipped the guest build
+fn get_sp1_elf() -> Option<Sp1Elf> {
+    let elf = Sp1Assets::get(SP1_ELF_NAME)?.data.into_owned();
+    Some(Sp1Elf {
+        elf,
+        signature: Sp1Assets::get("sp1-program.elf.sig").map(|file| file.data.into_owned()),
+        source: "embedded".to_string(),
+    })
+}
+
+/// Read the SP1 guest from `path`, either the ELF itself or a directory holding
+/// `sp1-program.elf`, and fall back to the embedded ELF when `path` is not set.
+///
+/// Fails when the ELF is missing or invalid, or when its SHA-256 differs from `expected_digest`.
+pub fn load_sp1_elf(path: Option<&Path>, expected_digest: Option<&str>) -> anyhow::Result<Sp1Elf> {
+    let sp1_elf = match path {
+        Some(path) => {
+            let elf_path = if path.is_dir() {
+                path.join(SP1_ELF_NAME)
+            } else {
+                path.to_path_buf()
+            };
+            let elf = fs::read(&elf_path)
+                .with_context(|| format!("Failed to read SP1 ELF {}", elf_path.display()))?;
+            validate_elf(&elf)
+                .with_context(|| format!("Invalid SP1 ELF {}", elf_path.display()))?;
+            Sp1Elf {
+                elf,
+                // The signature file is optional
+                signature: fs::read(sig_path(&elf_path)).ok(),
+                source: elf_path.display().to_string(),
+            }
+        }
+        None => get_sp1_elf().context(
+            "This binary was built without the SP1 guest; point POOST_SP1_ELF at a prebuilt ELF",
+        )?,
+    };
+
+    if let Some(expected) = expected_digest {
+        let digest = elf_digest(&sp1_elf.elf);
+        if !digest.eq_ignore_ascii_case(expected) {
+            bail!(
+                "SP1 ELF {} has digest {}, expected {}",
+                sp1_elf.source,
+                digest,
+                expected
+            );
+        }
+    }
+    Ok(sp1_elf)
+}
+
+/// The public values the guest committed, read from a proof of `EreSP1`, which is a
+/// bincode encoded `SP1ProofWithPublicValues`
+pub fn sp1_public_values(proof: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let proof: SP1ProofWithPublicValues =
+        bincode::deserialize(proof).context("Failed to decode SP1 proof")?;
+    Ok(proof.public_values.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::test_elf;
+
+    #[test]
+    fn test_load_prebuilt_elf() {
+        let dir = tempfile::tempdir().unwrap();
+        let elf_path = dir.path().join(SP1_ELF_NAME);
+        fs::write(&elf_path, test_elf()).unwrap();
+        fs::write(dir.path().join("sp1-program.elf.sig"), b"signature").unwrap();
+        let digest = elf_digest(&test_elf());
+
+        let from_file = load_sp1_elf(Some(&elf_path), Some(&digest)).unwrap();
+        assert_eq!(from_file.elf, test_elf());
+        assert_eq!(
+            from_file.signature.as_deref(),
+            Some(b"signature".as_slice())
+        );
+
+        let from_dir = load_sp1_elf(Some(dir.path()), Some(&digest.to_uppercase())).unwrap();
+        assert_eq!(from_dir.elf, test_elf());
+    }
+
+    #[test]
+    fn test_digest_mismatch_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(SP1_ELF_NAME), test_elf()).unwrap();
+
+        let error = load_sp1_elf(Some(dir.path()), Some(&"00".repeat(32))).unwrap_err();
+        assert!(error.to_string().contains("expected"));
+    }
+
+    #[test]
+    fn test_missing_elf_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let error = load_sp1_elf(Some(dir.path()), None).unwrap_err();
+        assert!(error.to_string().contains("Failed to read SP1 ELF"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Which ELFs the server agrees to load.
+//!
+//! An ELF is trusted when its digest is on the allowlist, or when it comes with a
+//! detached ed25519 signature over the ELF bytes made by one of the trusted keys.
+//! Without an allowlist or trusted keys configured every ELF is trusted.
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+use super::elf_digest;
+
+#[derive(Debug, Clone, Default)]
+pub struct TrustPolicy {
+    /// Hex encoded SHA-256 digests of trusted ELFs
+    allowlist: HashSet<String>,
+    keys: Vec<VerifyingKey>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// Not on the allowlist and no signature was provided
+    NotAllowlisted,
+    /// The signature file is not a 64 byte ed25519 signature
+    MalformedSignature,
+    /// The signature was not made over this ELF by a trusted key
+    UntrustedSignature,
+}
+
+/// An ELF that the trust policy refused to load
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ElfRejection {
+    pub digest: String,
+    pub reason: RejectionReason,
+}
+
+impl fmt::Display for ElfRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            RejectionReason::NotAllowlisted => "is not allowlisted and has no signature",
+            RejectionReason::MalformedSignature => "has a malformed signature",
+            RejectionReason::UntrustedSignature => "is not signed by a trusted key",
+        };
+        write!(f, "ELF {} {}", self.digest, reason)
+    }
+}
+
+impl std::error::Error for ElfRejection {}
+
+impl TrustPolicy {
+    /// `allowlist` holds hex encoded SHA-256 digests, `keys` hex encoded ed25519 public keys
+    pub fn new(allowlist: &[String], keys: &[String]) -> anyhow::Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let bytes: [u8; 32] = hex::decode(key)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid ed25519 public key: {}", key))?;
+                VerifyingKey::from_bytes(&bytes)
+                    .map_err(|e| anyhow::anyhow!("Invalid ed25519 public key {}: {}", key, e))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            allowlist: allowlist.iter().map(|d| d.to_lowercase()).collect(),
+            keys,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.allowlist.is_empty() || !self.keys.is_empty()
+    }
+
+    /// Check `elf` against the policy. `signature` is the content of its detached
+    /// signature file, either the raw 64 bytes or their hex encoding.
+    pub fn check(&self, elf: &[u8], signature: Option<&[u8]>) -> Result<(), ElfRejection> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let digest = elf_digest(elf);
+        if self.allowlist.contains(&digest) {
+            return Ok(());
+        }
+
+        let reason = match signature.map(parse_signature) {
+            None => RejectionReason::NotAllowlisted,
+            Some(None) => RejectionReason::MalformedSignature,
+            Some(Some(signature)) => {
+                if self
+                    .keys
+                    .iter()
+                    .any(|key| key.verify(elf, &signature).is_ok())
+                {
+                    return Ok(());
+                }
+                RejectionReason::UntrustedSignature
+            }
+        };
+        tracing::warn!(digest = %digest, ?reason, "Rejected untrusted ELF");
+        Err(ElfRejection { digest, reason })
+    }
+}
+
+fn parse_signature(bytes: &[u8]) -> Option<Signature> {
+    let bytes: [u8; 64] = match bytes.try_into() {
+        Ok(raw) => raw,
+        Err(_) => hex::decode(std::str::from_utf8(bytes).ok()?.trim())
+            .ok()?
+            .try_into()
+            .ok()?,
+    };
+    Some(Signature::from_bytes(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::test_elf;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn policy(allowlist: &[String], keys: &[&SigningKey]) -> TrustPolicy {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| hex::encode(key.verifying_key().as_bytes()))
+            .collect();
+        TrustPolicy::new(allowlist, &keys).unwrap()
+    }
+
+    #[test]
+    fn test_disabled_policy_trusts_everything() {
+        assert!(TrustPolicy::default().check(&test_elf(), None).is_ok());
+    }
+
+    #[test]
+    fn test_allowlisted_digest() {
+        let elf = test_elf();
+        let policy = policy(&[elf_digest(&elf).to_uppercase()], &[]);
+
+        assert!(policy.check(&elf, None).is_ok());
+
+        let mut other = elf.clone();
+        other.push(0);
+        let rejection = policy.check(&other, None).unwrap_err();
+        assert_eq!(rejection.digest, elf_digest(&other));
+        assert_eq!(rejection.reason, RejectionReason::NotAllowlisted);
+    }
+
+    #[test]
+    fn test_detached_signature() {
+        let elf = test_elf();
+        let trusted = signing_key(1);
+        let policy = policy(&[], &[&trusted]);
+
+        let raw = trusted.sign(&elf).to_bytes();
+        assert!(policy.check(&elf, Some(&raw)).is_ok());
+        let encoded = format!("{}\n", hex::encode(raw));
+        assert!(policy.check(&elf, Some(encoded.as_bytes())).is_ok());
+
+        let untrusted = signing_key(2).sign(&elf).to_bytes();
+        let rejection = policy.check(&elf, Some(&untrusted)).unwrap_err();
+        assert_eq!(rejection.reason, RejectionReason::UntrustedSignature);
+
+        let rejection = policy.check(&elf, Some(b"garbage")).unwrap_err();
+        assert_eq!(rejection.reason, RejectionReason::MalformedSignature);
+    }
+
+    #[test]
+    fn test_invalid_key_is_rejected() {
+        assert!(TrustPolicy::new(&[], &["abcd".to_string()]).is_err());
+    }
+}
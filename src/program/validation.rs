@@ -0,0 +1,295 @@
+//! Checks on a `ClientInput` that catch broken inputs before they reach the guest.
+//!
+//! They only cover the consistency of the input itself; whether the block is valid
+//! against its pre-state is up to the guest.
+
+use alloy_consensus::proofs::calculate_transaction_root;
+use alloy_consensus::{Header, Transaction};
+use alloy_rlp::Decodable;
+use reth_stateless::ClientInput;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A problem with a request input, located by its JSON path
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputError {
+    /// For example `input.witness.state[1234]`
+    pub path: String,
+    pub message: String,
+}
+
+impl InputError {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Locate a serde error at `path` below `input`, as tracked by `serde_path_to_error`
+    pub fn at(path: &serde_path_to_error::Path, message: impl fmt::Display) -> Self {
+        let path = path.to_string();
+        let path = match path.as_str() {
+            "." => "input".to_string(),
+            _ if path.starts_with('[') => format!("input{path}"),
+            _ => format!("input.{path}"),
+        };
+        Self::new(path, message.to_string())
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Check that the witness has what the guest needs and that the block is consistent, and
+/// belongs to the chain `chain_id`. Errors are located below `path`.
+pub fn validate_client_input(input: &ClientInput, chain_id: u64, path: &str) -> Vec<InputError> {
+    let mut errors = Vec::new();
+    let header = &input.block.header;
+    let witness = &input.witness;
+
+    if witness.state.is_empty() {
+        errors.push(InputError::new(
+            format!("{path}.witness.state"),
+            "must not be empty",
+        ));
+    }
+
+    // The ancestor headers must form a chain ending in the parent of the block
+    let mut ancestors: Vec<Header> = Vec::with_capacity(witness.headers.len());
+    for (i, encoded) in witness.headers.iter().enumerate() {
+        match Header::decode(&mut encoded.as_ref()) {
+            Ok(ancestor) => ancestors.push(ancestor),
+            Err(e) => errors.push(InputError::new(
+                format!("{path}.witness.headers[{i}]"),
+                format!("invalid RLP header: {e}"),
+            )),
+        }
+    }
+    if witness.headers.is_empty() {
+        errors.push(InputError::new(
+            format!("{path}.witness.headers"),
+            "must contain at least the parent header",
+        ));
+    } else if ancestors.len() == witness.headers.len() {
+        for (i, pair) in ancestors.windows(2).enumerate() {
+            if pair[1].parent_hash != pair[0].hash_slow() {
+                errors.push(InputError::new(
+                    format!("{path}.witness.headers[{}]", i + 1),
+                    "does not follow the previous header",
+                ));
+            }
+        }
+        let parent = ancestors.last().expect("headers is not empty");
+        if header.parent_hash != parent.hash_slow() {
+            errors.push(InputError::new(
+                format!("{path}.block.header.parentHash"),
+                format!(
+                    "does not match the hash {} of the last witness header",
+                    parent.hash_slow()
+                ),
+            ));
+        }
+        if header.number != parent.number + 1 {
+            errors.push(InputError::new(
+                format!("{path}.block.header.number"),
+                format!(
+                    "expected {}, after the last witness header",
+                    parent.number + 1
+                ),
+            ));
+        }
+    }
+
+    let transactions = &input.block.body.transactions;
+    if calculate_transaction_root(transactions) != header.transactions_root {
+        errors.push(InputError::new(
+            format!("{path}.block.body.transactions"),
+            format!(
+                "{} transactions do not match the transactions root of the header",
+                transactions.len()
+            ),
+        ));
+    }
+
+    for (i, transaction) in transactions.iter().enumerate() {
+        if let Some(id) = transaction.chain_id().filter(|id| *id != chain_id) {
+            errors.push(InputError::new(
+                format!("{path}.block.body.transactions[{i}]"),
+                format!("is signed for chain {id}, not chain {chain_id}"),
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Check every block of a range, and that each block is the child of the previous one
+pub fn validate_block_range(blocks: &[ClientInput], chain_id: u64) -> Vec<InputError> {
+    let mut errors = Vec::new();
+    for (i, input) in blocks.iter().enumerate() {
+        errors.extend(validate_client_input(
+            input,
+            chain_id,
+            &format!("input.blocks[{i}]"),
+        ));
+    }
+    for (i, pair) in blocks.windows(2).enumerate() {
+        let (previous, header) = (&pair[0].block.header, &pair[1].block.header);
+        if header.parent_hash != previous.hash_slow() {
+            errors.push(InputError::new(
+                format!("input.blocks[{}].block.header.parentHash", i + 1),
+                format!(
+                    "does not match the hash {} of the previous block",
+                    previous.hash_slow()
+                ),
+            ));
+        }
+    }
+    errors
+}
+
+/// A consistent input: an empty block on top of a chain of two ancestors. Its headers are
+/// otherwise default, so it passes the checks of this module and `mock_block_validation`, but
+/// not the consensus checks of reth.
+#[cfg(test)]
+pub fn test_client_input() -> ClientInput {
+    use alloy_rlp::Encodable;
+
+    let encode = |header: &Header| {
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        out.into()
+    };
+    let grandparent = Header {
+        number: 8,
+        ..Default::default()
+    };
+    let parent = Header {
+        number: 9,
+        parent_hash: grandparent.hash_slow(),
+        ..Default::default()
+    };
+    let mut input = ClientInput {
+        block: Default::default(),
+        witness: Default::default(),
+    };
+    input.block.header.number = 10;
+    input.block.header.parent_hash = parent.hash_slow();
+    input.block.header.transactions_root =
+        calculate_transaction_root(&input.block.body.transactions);
+    input.witness.state = vec![vec![1].into()];
+    input.witness.headers = vec![encode(&grandparent), encode(&parent)];
+    input
+}
+
+/// A consistent range: the block of `test_client_input`, and an empty child on top of it
+#[cfg(test)]
+pub fn test_block_range() -> Vec<ClientInput> {
+    use alloy_rlp::Encodable;
+
+    let first = test_client_input();
+    let mut parent = Vec::new();
+    first.block.header.encode(&mut parent);
+
+    let mut second = test_client_input();
+    second.block.header.number = 11;
+    second.block.header.parent_hash = first.block.header.hash_slow();
+    second.witness.headers = vec![first.witness.headers[1].clone(), parent.into()];
+    vec![first, second]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(errors: &[InputError]) -> Vec<&str> {
+        errors.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_input() {
+        assert_eq!(
+            validate_client_input(&test_client_input(), 1, "input"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_missing_witness_parts() {
+        let mut input = test_client_input();
+        input.witness.state.clear();
+        input.witness.headers.clear();
+
+        assert_eq!(
+            paths(&validate_client_input(&input, 1, "input")),
+            vec!["input.witness.state", "input.witness.headers"]
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_block() {
+        let mut input = test_client_input();
+        input.block.header.number = 11;
+        input.block.header.parent_hash = Default::default();
+        input.witness.headers.swap(0, 1);
+
+        assert_eq!(
+            paths(&validate_client_input(&input, 1, "input")),
+            vec![
+                "input.witness.headers[1]",
+                "input.block.header.parentHash",
+                "input.block.header.number",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_range() {
+        let mut blocks = super::test_block_range();
+        assert_eq!(validate_block_range(&blocks, 1), vec![]);
+
+        blocks[1].witness.state.clear();
+        assert_eq!(
+            paths(&validate_block_range(&blocks, 1)),
+            vec!["input.blocks[1].witness.state"]
+        );
+
+        blocks.swap(0, 1);
+        assert_eq!(
+            paths(&validate_block_range(&blocks, 1)),
+            vec![
+                "input.blocks[0].witness.state",
+                "input.blocks[1].block.header.parentHash",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_encoding() {
+        let mut input = test_client_input();
+        input.witness.headers[1] = vec![0xff].into();
+
+        let errors = validate_client_input(&input, 1, "input");
+        assert_eq!(paths(&errors), vec!["input.witness.headers[1]"]);
+        assert!(errors[0].message.starts_with("invalid RLP header"));
+    }
+
+    #[test]
+    fn test_error_paths() {
+        let value = serde_json::json!({"a": [1, "x"]});
+        let error =
+            serde_path_to_error::deserialize::<_, std::collections::HashMap<String, Vec<u8>>>(
+                &value,
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            InputError::at(error.path(), error.inner()).to_string(),
+            "input.a[1]: invalid type: string \"x\", expected u8"
+        );
+    }
+}
@@ -0,0 +1,608 @@
+//! Reloading the programs directory while the server runs.
+//!
+//! A reload reads every manifest again and swaps the resulting set of programs
+//! into `AppState.programs` in one step. Requests that already looked up a
+//! program keep using the instance they got, so in-flight executions and proofs
+//! finish on the old ELF. Programs whose ELF did not change keep their instance.
+//!
+//! A version dropped from the manifests is retired rather than unloaded: it loses its
+//! aliases but stays registered while an unfinished job refers to it, so that queued and
+//! resumed proofs still find it, and for a grace period after that for requests pinning it.
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{RwLock, mpsc};
+
+use crate::common::{ProgramID, Programs, zkVMInstance};
+use crate::config::{Config, MockConfig};
+use crate::jobs::{JobStore, now_millis};
+use crate::program::{ElfRejection, LoadedProgram, Resource, TrustPolicy, load_manifests};
+
+// Number of reloads kept in the history
+const HISTORY_LIMIT: usize = 100;
+// Quiet time after a file change before reloading, so that a copy of several
+// files results in a single reload
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadTrigger {
+    Startup,
+    /// SIGHUP
+    Signal,
+    FileChange,
+}
+
+/// Outcome of one reload, as changes to the aliases. Timestamps are
+/// milliseconds since the unix epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadEvent {
+    pub at: u64,
+    pub trigger: ReloadTrigger,
+    pub added: Vec<ProgramID>,
+    /// Aliases now pointing at a different version
+    pub updated: Vec<ProgramID>,
+    pub removed: Vec<ProgramID>,
+    /// Versions no longer in the manifests, kept loaded while they may still be needed
+    pub retired: Vec<String>,
+    /// Set when the reload failed and the previous programs were kept
+    pub error: Option<String>,
+    /// Set when the reload failed because an ELF is not trusted
+    pub rejected_elf: Option<ElfRejection>,
+}
+
+#[derive(Clone)]
+struct Loaded {
+    resource: Resource,
+    instance: zkVMInstance,
+}
+
+#[derive(Clone, Default)]
+struct Registry {
+    // Programs compiled into the binary by alias, with their version; kept across reloads
+    builtin: HashMap<ProgramID, (String, zkVMInstance)>,
+    // Instances of the manifest programs by version, as of the last successful reload
+    manifests: HashMap<String, Loaded>,
+    // Aliases repointed through the admin API, which win over the manifests
+    repointed: HashMap<ProgramID, String>,
+    // Versions dropped from the manifests but still registered, with when they were dropped
+    retired: HashMap<String, u64>,
+}
+
+pub struct Reloader {
+    dir: PathBuf,
+    trust: TrustPolicy,
+    // Settings for manifests that use the mock backend
+    mock: MockConfig,
+    retired_ttl: Duration,
+    // Also serializes reloads and alias changes
+    registry: tokio::sync::Mutex<Registry>,
+    history: Mutex<VecDeque<ReloadEvent>>,
+    instantiate: fn(&LoadedProgram, &MockConfig) -> anyhow::Result<zkVMInstance>,
+}
+
+impl Reloader {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            dir: config.programs_dir.clone(),
+            trust: config.elf_trust.clone(),
+            mock: config.mock.clone(),
+            retired_ttl: config.retired_version_ttl,
+            registry: Default::default(),
+            history: Default::default(),
+            instantiate: LoadedProgram::instantiate,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Register a program that does not come from a manifest
+    pub async fn add_builtin(
+        &self,
+        program_id: ProgramID,
+        version: String,
+        instance: zkVMInstance,
+    ) {
+        self.registry
+            .lock()
+            .await
+            .builtin
+            .insert(program_id, (version, instance));
+    }
+
+    /// Point `alias` at `version`, creating the alias if needed. The change is
+    /// kept across reloads, but not across restarts.
+    ///
+    /// Returns false if the version is not registered.
+    pub async fn set_alias(
+        &self,
+        programs: &RwLock<Programs>,
+        alias: ProgramID,
+        version: &str,
+    ) -> bool {
+        let mut registry = self.registry.lock().await;
+        if !programs.write().await.set_alias(alias.clone(), version) {
+            return false;
+        }
+        println!("Alias {:?} now points at version {}", alias, version);
+        registry.repointed.insert(alias, version.to_string());
+        true
+    }
+
+    /// Read the manifests again and swap the programs into `programs`.
+    ///
+    /// On error nothing is swapped and the previous programs stay in place.
+    /// Either way the reload is recorded in the history.
+    pub async fn reload(
+        self: &Arc<Self>,
+        programs: &RwLock<Programs>,
+        jobs: &JobStore,
+        trigger: ReloadTrigger,
+    ) -> anyhow::Result<ReloadEvent> {
+        let mut registry = self.registry.lock().await;
+        // Reading the ELFs and setting up provers blocks, so it runs off the async workers.
+        // The registry stays locked until the result is swapped in.
+        let (reloader, snapshot) = (self.clone(), registry.clone());
+        let result = tokio::task::spawn_blocking(move || reloader.load(&snapshot))
+            .await
+            .map_err(|e| anyhow::anyhow!("Reload task failed: {}", e))
+            .and_then(|result| result);
+
+        let mut event = ReloadEvent {
+            at: now_millis(),
+            trigger,
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+            retired: Vec::new(),
+            error: None,
+            rejected_elf: None,
+        };
+        let outcome = match result {
+            Ok((mut swapped, manifests)) => {
+                let mut current = programs.write().await;
+                let (old, new) = (current.aliases(), swapped.aliases());
+                for (alias, version) in new {
+                    match old.get(alias) {
+                        None => event.added.push(alias.clone()),
+                        Some(old_version) if old_version != version => {
+                            event.updated.push(alias.clone())
+                        }
+                        Some(_) => {}
+                    }
+                }
+                event.removed = old
+                    .keys()
+                    .filter(|alias| !new.contains_key(*alias))
+                    .cloned()
+                    .collect();
+                for list in [&mut event.added, &mut event.updated, &mut event.removed] {
+                    list.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+
+                // Versions back in the manifests are no longer retired
+                registry
+                    .retired
+                    .retain(|version, _| !swapped.has_version(version));
+                let now = now_millis();
+                for (version, instance) in current.versions() {
+                    if !swapped.has_version(version) {
+                        if !registry.retired.contains_key(version) {
+                            registry.retired.insert(version.clone(), now);
+                            event.retired.push(version.clone());
+                        }
+                        swapped.add_version(version.clone(), instance.clone());
+                    }
+                }
+                event.retired.sort();
+
+                // Requests may keep pinning the versions the aliases pointed at
+                swapped.inherit_served(&current);
+                *current = swapped;
+                registry.manifests = manifests;
+                self.prune(&mut registry, &mut current, jobs);
+                Ok(event.clone())
+            }
+            Err(e) => {
+                event.error = Some(format!("{:#}", e));
+                event.rejected_elf = e.downcast_ref::<ElfRejection>().cloned();
+                Err(e)
+            }
+        };
+
+        let mut history = self.history.lock().expect("reload history poisoned");
+        if history.len() == HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(event);
+        outcome
+    }
+
+    /// Unload the retired versions that no unfinished job refers to and whose grace period
+    /// is over
+    pub async fn prune_retired(&self, programs: &RwLock<Programs>, jobs: &JobStore) {
+        let mut registry = self.registry.lock().await;
+        if registry.retired.is_empty() {
+            return;
+        }
+        self.prune(&mut registry, &mut *programs.write().await, jobs);
+    }
+
+    fn prune(&self, registry: &mut Registry, programs: &mut Programs, jobs: &JobStore) {
+        let pinned: HashSet<String> = jobs
+            .unfinished()
+            .into_iter()
+            .filter_map(|job| job.version)
+            .collect();
+        let expired_before = now_millis().saturating_sub(self.retired_ttl.as_millis() as u64);
+        registry.retired.retain(|version, retired_at| {
+            if pinned.contains(version) || *retired_at > expired_before {
+                return true;
+            }
+            // A version an alias was repointed at stays until the alias moves on
+            if !programs.remove_version(version) {
+                return true;
+            }
+            println!("Unloaded retired version {}", version);
+            false
+        });
+    }
+
+    /// Past reloads, oldest first
+    pub fn history(&self) -> Vec<ReloadEvent> {
+        let history = self.history.lock().expect("reload history poisoned");
+        history.iter().cloned().collect()
+    }
+
+    fn load(&self, registry: &Registry) -> anyhow::Result<(Programs, HashMap<String, Loaded>)> {
+        let mut programs = Programs::default();
+        for (alias, (version, instance)) in &registry.builtin {
+            programs.add_version(version.clone(), instance.clone());
+            programs.set_alias(alias.clone(), version);
+        }
+
+        let mut manifests = HashMap::new();
+        for program in load_manifests(&self.dir, &self.trust)? {
+            let names: Vec<ProgramID> = std::iter::once(program.program_id.clone())
+                .chain(program.aliases.iter().cloned())
+                .collect();
+            if let Some(name) = names
+                .iter()
+                .find(|name| registry.builtin.contains_key(*name))
+            {
+                anyhow::bail!(
+                    "Program {} in {} is already registered",
+                    name.0,
+                    program.manifest_path.display()
+                );
+            }
+            if programs
+                .versions()
+                .any(|(version, _)| *version == program.digest)
+            {
+                anyhow::bail!(
+                    "The ELF of {} is already registered",
+                    program.manifest_path.display()
+                );
+            }
+
+            // Keep the warm instance of a program whose ELF did not change
+            let instance = match registry.manifests.get(&program.digest) {
+                Some(old) if old.resource == program.resource => old.instance.clone(),
+                _ => {
+                    let instance = (self.instantiate)(&program, &self.mock)?;
+                    println!(
+                        "Loaded {} program {:?} version {} taking {:?} input from {}",
+                        program.vendor,
+                        program.program_id,
+                        program.digest,
+                        program.input,
+                        program.manifest_path.display()
+                    );
+                    instance
+                }
+            };
+            programs.add_version(program.digest.clone(), instance.clone());
+            for name in names {
+                programs.set_alias(name, &program.digest);
+            }
+            manifests.insert(
+                program.digest,
+                Loaded {
+                    resource: program.resource,
+                    instance,
+                },
+            );
+        }
+
+        for (alias, version) in &registry.repointed {
+            if !programs.set_alias(alias.clone(), version) {
+                tracing::warn!(
+                    "Alias {:?} was pointed at version {}, which is no longer registered",
+                    alias,
+                    version
+                );
+            }
+        }
+        Ok((programs, manifests))
+    }
+}
+
+/// Reload the programs whenever a file in the programs directory changes.
+///
+/// Only the directory itself is watched; manifests or ELFs kept elsewhere are
+/// picked up on SIGHUP.
+pub fn watch(
+    reloader: Arc<Reloader>,
+    programs: Arc<RwLock<Programs>>,
+    jobs: Arc<JobStore>,
+) -> notify::Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher: RecommendedWatcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if !event.kind.is_access() => {
+                let _ = tx.send(());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Error watching programs: {}", e),
+        })?;
+    watcher.watch(reloader.dir(), RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        // Dropping the watcher stops it
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            loop {
+                match tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            log_reload(
+                reloader
+                    .reload(&programs, &jobs, ReloadTrigger::FileChange)
+                    .await,
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Print the outcome of a reload that happened while the server runs
+pub fn log_reload(result: anyhow::Result<ReloadEvent>) {
+    match result {
+        Ok(event) => println!(
+            "Reloaded programs: {} added, {} updated, {} removed",
+            event.added.len(),
+            event.updated.len(),
+            event.removed.len()
+        ),
+        Err(e) => tracing::warn!(
+            "Reloading programs failed, keeping the previous ones: {:#}",
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{ClientID, zkVMVendor};
+    use crate::jobs::JobKind;
+    use crate::mock_zkvm::MockZkVM;
+    use crate::program::{elf_digest, test_elf};
+    use std::fs;
+
+    fn mock_instance(_: &LoadedProgram, _: &MockConfig) -> anyhow::Result<zkVMInstance> {
+        Ok(zkVMInstance::new(
+            zkVMVendor::SP1,
+            Arc::new(MockZkVM::default()),
+        ))
+    }
+
+    fn reloader(dir: &Path) -> Arc<Reloader> {
+        Arc::new(Reloader {
+            instantiate: mock_instance,
+            ..Reloader::new(&Config {
+                programs_dir: dir.to_path_buf(),
+                ..Config::default()
+            })
+        })
+    }
+
+    fn write_program(dir: &Path, name: &str, elf: &[u8]) {
+        fs::write(dir.join(format!("{name}.elf")), elf).unwrap();
+        fs::write(
+            dir.join(format!("{name}.toml")),
+            format!("name = \"{name}\"\nvendor = \"sp1\"\nelf = \"{name}.elf\"\n"),
+        )
+        .unwrap();
+    }
+
+    // A valid ELF, distinct for every `tag`
+    fn elf(tag: &str) -> Vec<u8> {
+        let mut elf = test_elf();
+        elf.extend_from_slice(tag.as_bytes());
+        elf
+    }
+
+    fn ids(names: &[&str]) -> Vec<ProgramID> {
+        names
+            .iter()
+            .map(|name| ProgramID(name.to_string()))
+            .collect()
+    }
+
+    fn version_of(programs: &Programs, alias: &str) -> Option<String> {
+        programs
+            .aliases()
+            .get(&ProgramID(alias.to_string()))
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = reloader(dir.path());
+        let programs = RwLock::new(Programs::default());
+        let jobs = JobStore::new(10);
+        reloader
+            .add_builtin(
+                ProgramID("sp1".to_string()),
+                "builtin".to_string(),
+                zkVMInstance::new(zkVMVendor::SP1, Arc::new(MockZkVM::default())),
+            )
+            .await;
+        write_program(dir.path(), "a", &elf("a1"));
+        write_program(dir.path(), "b", &elf("b"));
+
+        let event = reloader
+            .reload(&programs, &jobs, ReloadTrigger::Startup)
+            .await
+            .unwrap();
+        assert_eq!(event.added, ids(&["a", "b", "sp1"]));
+        assert_eq!(programs.read().await.versions().count(), 3);
+
+        // Change the ELF of `a` and remove `b`
+        write_program(dir.path(), "a", &elf("a2"));
+        fs::remove_file(dir.path().join("b.toml")).unwrap();
+
+        let event = reloader
+            .reload(&programs, &jobs, ReloadTrigger::Signal)
+            .await
+            .unwrap();
+        assert!(event.added.is_empty());
+        assert_eq!(event.updated, ids(&["a"]));
+        assert_eq!(event.removed, ids(&["b"]));
+        let programs = programs.read().await;
+        assert_eq!(version_of(&programs, "sp1").as_deref(), Some("builtin"));
+        assert_eq!(version_of(&programs, "a"), Some(elf_digest(&elf("a2"))));
+        assert_eq!(version_of(&programs, "b"), None);
+        // Retired, but still registered for a while
+        let mut retired = [elf_digest(&elf("a1")), elf_digest(&elf("b"))];
+        retired.sort();
+        assert_eq!(event.retired, retired);
+        assert!(programs.has_version(&elf_digest(&elf("b"))));
+    }
+
+    #[tokio::test]
+    async fn test_retired_version_kept_for_pinned_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = Arc::new(Reloader {
+            retired_ttl: Duration::ZERO,
+            ..Arc::into_inner(reloader(dir.path())).unwrap()
+        });
+        let programs = RwLock::new(Programs::default());
+        let jobs = Arc::new(JobStore::new(10));
+        write_program(dir.path(), "a", &elf("a1"));
+        reloader
+            .reload(&programs, &jobs, ReloadTrigger::Startup)
+            .await
+            .unwrap();
+
+        let v1 = elf_digest(&elf("a1"));
+        let job = jobs.submit(
+            JobKind::Prove,
+            ProgramID("a".to_string()),
+            Some(v1.clone()),
+            ClientID::default(),
+            None,
+            None,
+        );
+
+        // The job still needs the version the manifests no longer have
+        write_program(dir.path(), "a", &elf("a2"));
+        let event = reloader
+            .reload(&programs, &jobs, ReloadTrigger::Signal)
+            .await
+            .unwrap();
+        assert_eq!(event.updated, ids(&["a"]));
+        assert_eq!(event.retired, std::slice::from_ref(&v1));
+        assert!(programs.read().await.has_version(&v1));
+        reloader.prune_retired(&programs, &jobs).await;
+        assert!(programs.read().await.has_version(&v1));
+
+        // Unloaded once the job is done
+        job.failed("done".to_string());
+        reloader.prune_retired(&programs, &jobs).await;
+        let programs = programs.read().await;
+        assert!(!programs.has_version(&v1));
+        assert_eq!(version_of(&programs, "a"), Some(elf_digest(&elf("a2"))));
+    }
+
+    #[tokio::test]
+    async fn test_repointed_alias_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = reloader(dir.path());
+        let programs = RwLock::new(Programs::default());
+        let jobs = JobStore::new(10);
+        write_program(dir.path(), "v1", &elf("v1"));
+        write_program(dir.path(), "v2", &elf("v2"));
+        reloader
+            .reload(&programs, &jobs, ReloadTrigger::Startup)
+            .await
+            .unwrap();
+
+        let alias = ProgramID("mainnet".to_string());
+        let v2 = elf_digest(&elf("v2"));
+        assert!(reloader.set_alias(&programs, alias.clone(), &v2).await);
+        assert!(
+            !reloader
+                .set_alias(&programs, alias.clone(), "unknown")
+                .await
+        );
+
+        reloader
+            .reload(&programs, &jobs, ReloadTrigger::Signal)
+            .await
+            .unwrap();
+        assert_eq!(
+            version_of(&*programs.read().await, "mainnet"),
+            Some(v2.clone())
+        );
+
+        // The version an alias pointed at before a reload can still be pinned after it
+        let v1 = elf_digest(&elf("v1"));
+        assert!(reloader.set_alias(&programs, alias.clone(), &v1).await);
+        reloader
+            .reload(&programs, &jobs, ReloadTrigger::Signal)
+            .await
+            .unwrap();
+        assert!(programs.read().await.has_served(&alias, &v2));
+    }
+
+    #[tokio::test]
+    async fn test_failed_reload_keeps_programs() {
+        let dir = tempfile::tempdir().unwrap();
+        let reloader = reloader(dir.path());
+        let programs = RwLock::new(Programs::default());
+        let jobs = JobStore::new(10);
+        write_program(dir.path(), "a", &elf("a"));
+        reloader
+            .reload(&programs, &jobs, ReloadTrigger::Startup)
+            .await
+            .unwrap();
+
+        write_program(dir.path(), "b", b"not an elf");
+        assert!(
+            reloader
+                .reload(&programs, &jobs, ReloadTrigger::FileChange)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(programs.read().await.aliases().len(), 1);
+        let history = reloader.history();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].error.is_none());
+        assert_eq!(history[1].trigger, ReloadTrigger::FileChange);
+        assert!(history[1].error.as_ref().unwrap().contains("b.toml"));
+    }
+}
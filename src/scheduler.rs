@@ -0,0 +1,505 @@
+//! Orders waiting proving work in front of `zkVM::prove`.
+//!
+//! Waiting jobs are ordered by priority first. Within a priority level the client
+//! with the fewest running proofs goes next, then the client that was served least
+//! recently, so one client's backfill cannot starve everybody else. Remaining ties
+//! are broken by age.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::common::{ClientID, ProgramID};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// A request to run one proof
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub job_id: Uuid,
+    pub client_id: ClientID,
+    pub program_id: ProgramID,
+    pub priority: Priority,
+}
+
+/// A job waiting for a proving slot, as reported to admins
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub job_id: Uuid,
+    pub position: usize,
+    pub client_id: ClientID,
+    pub program_id: ProgramID,
+    pub priority: Priority,
+    pub waiting_time: Duration,
+}
+
+/// Order of the waiting jobs of one client; the smallest runs first
+type WaitKey = (Reverse<Priority>, u64);
+
+#[derive(Debug)]
+struct Waiting {
+    ticket: Ticket,
+    enqueued_at: Instant,
+    // Wakes the waiter once the job is admitted
+    admit: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct ClientShare {
+    running: usize,
+    // Value of `Queue::tick` when the client last had a job admitted
+    last_admitted: u64,
+    waiting: BTreeMap<WaitKey, Waiting>,
+}
+
+#[derive(Debug)]
+struct Queue {
+    slots: usize,
+    // While held, no waiting job is admitted
+    held: bool,
+    running: usize,
+    next_seq: u64,
+    tick: u64,
+    clients: HashMap<ClientID, ClientShare>,
+    // Where each waiting job is queued
+    waiting: HashMap<Uuid, (ClientID, WaitKey)>,
+}
+
+impl Queue {
+    /// Scheduling key of a waiting job; the smallest key runs next
+    fn key(share: &ClientShare, (priority, seq): WaitKey) -> (Reverse<Priority>, usize, u64, u64) {
+        (priority, share.running, share.last_admitted, seq)
+    }
+
+    /// The client whose first waiting job runs next. Only the first job of each client is a
+    /// candidate, since the jobs of a client share its running count and last admission.
+    fn next(&self) -> Option<ClientID> {
+        self.clients
+            .iter()
+            .filter_map(|(client_id, share)| {
+                let (key, _) = share.waiting.first_key_value()?;
+                Some((Self::key(share, *key), client_id))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, client_id)| client_id.clone())
+    }
+
+    /// Admit waiting jobs while slots are free, waking only the admitted waiters
+    fn dispatch(&mut self) {
+        while !self.held && self.running < self.slots {
+            let Some(client_id) = self.next() else {
+                break;
+            };
+            self.tick += 1;
+            self.running += 1;
+            let share = self
+                .clients
+                .get_mut(&client_id)
+                .expect("next client exists");
+            let (_, job) = share.waiting.pop_first().expect("next client has a job");
+            share.running += 1;
+            share.last_admitted = self.tick;
+            self.waiting.remove(&job.ticket.job_id);
+            // The waiter leaves the queue before its receiver is dropped, so this never fails
+            let _ = job.admit.send(());
+        }
+    }
+
+    /// Take a waiting job out of the queue
+    fn remove(&mut self, job_id: Uuid) -> Option<Waiting> {
+        let (client_id, key) = self.waiting.remove(&job_id)?;
+        let job = self.clients.get_mut(&client_id)?.waiting.remove(&key);
+        self.prune(&client_id);
+        job
+    }
+
+    /// Give back the slot of a job of `client_id`
+    fn release(&mut self, client_id: &ClientID) {
+        self.running -= 1;
+        if let Some(share) = self.clients.get_mut(client_id) {
+            share.running -= 1;
+        }
+        self.prune(client_id);
+    }
+
+    /// Forget clients that have nothing running or waiting
+    fn prune(&mut self, client_id: &ClientID) {
+        let idle = self
+            .clients
+            .get(client_id)
+            .is_some_and(|s| s.running == 0 && s.waiting.is_empty());
+        if idle {
+            self.clients.remove(client_id);
+        }
+    }
+}
+
+pub struct Scheduler {
+    queue: Mutex<Queue>,
+}
+
+impl Scheduler {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            queue: Mutex::new(Queue {
+                slots,
+                held: false,
+                running: 0,
+                next_seq: 0,
+                tick: 0,
+                clients: HashMap::new(),
+                waiting: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Wait until the scheduler hands `ticket` a proving slot.
+    ///
+    /// The slot is released when the returned permit is dropped. If this future is
+    /// dropped while waiting (for example because the client went away), the job
+    /// leaves the queue.
+    pub async fn acquire(self: &Arc<Self>, ticket: Ticket) -> Permit {
+        let job_id = ticket.job_id;
+        let client_id = ticket.client_id.clone();
+        let (admit, admitted) = oneshot::channel();
+        {
+            let mut queue = self.lock();
+            let key = (Reverse(ticket.priority), queue.next_seq);
+            queue.next_seq += 1;
+            queue.waiting.insert(job_id, (client_id.clone(), key));
+            let job = Waiting {
+                ticket,
+                enqueued_at: Instant::now(),
+                admit,
+            };
+            let share = queue.clients.entry(client_id.clone()).or_default();
+            share.waiting.insert(key, job);
+            queue.dispatch();
+        }
+
+        let mut guard = WaitGuard {
+            scheduler: self,
+            job_id,
+            client_id: client_id.clone(),
+            admitted: false,
+        };
+        // The sender is only dropped once the job is admitted or has left the queue
+        let _ = admitted.await;
+        guard.admitted = true;
+
+        Permit {
+            scheduler: Arc::clone(self),
+            client_id,
+        }
+    }
+
+    /// Change the priority of a waiting job. Returns false if the job is not queued.
+    pub fn reprioritize(&self, job_id: Uuid, priority: Priority) -> bool {
+        let mut queue = self.lock();
+        let Some((client_id, key)) = queue.waiting.get(&job_id).cloned() else {
+            return false;
+        };
+        let new_key = (Reverse(priority), key.1);
+        let share = queue
+            .clients
+            .get_mut(&client_id)
+            .expect("waiting client exists");
+        let mut job = share.waiting.remove(&key).expect("waiting job is queued");
+        job.ticket.priority = priority;
+        share.waiting.insert(new_key, job);
+        queue.waiting.insert(job_id, (client_id, new_key));
+        queue.dispatch();
+        true
+    }
+
+    /// Waiting jobs in the order they will be admitted
+    pub fn queued(&self) -> Vec<QueuedJob> {
+        let queue = self.lock();
+        let mut waiting: Vec<_> = queue
+            .clients
+            .values()
+            .flat_map(|share| {
+                share
+                    .waiting
+                    .iter()
+                    .map(move |(key, job)| (Queue::key(share, *key), job))
+            })
+            .collect();
+        waiting.sort_by_key(|(key, _)| *key);
+        waiting
+            .into_iter()
+            .enumerate()
+            .map(|(position, (_, job))| QueuedJob {
+                job_id: job.ticket.job_id,
+                position,
+                client_id: job.ticket.client_id.clone(),
+                program_id: job.ticket.program_id.clone(),
+                priority: job.ticket.priority,
+                waiting_time: job.enqueued_at.elapsed(),
+            })
+            .collect()
+    }
+
+    /// Stop admitting waiting jobs, or start again. Running proofs are not affected.
+    pub fn hold(&self, held: bool) {
+        let mut queue = self.lock();
+        queue.held = held;
+        queue.dispatch();
+    }
+
+    /// Number of proofs currently holding a slot
+    pub fn running(&self) -> usize {
+        self.lock().running
+    }
+
+    fn release(&self, client_id: &ClientID) {
+        let mut queue = self.lock();
+        queue.release(client_id);
+        queue.dispatch();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().expect("scheduler queue poisoned")
+    }
+}
+
+/// A proving slot; dropping it lets the next job in line run
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    client_id: ClientID,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.client_id);
+    }
+}
+
+struct WaitGuard<'a> {
+    scheduler: &'a Scheduler,
+    job_id: Uuid,
+    client_id: ClientID,
+    // Set once the waiter holds its permit
+    admitted: bool,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+        let mut queue = self.scheduler.lock();
+        // A job admitted after its waiter went away gives its slot to the next one
+        if queue.remove(self.job_id).is_none() {
+            queue.release(&self.client_id);
+        }
+        queue.dispatch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn ticket(client: &str, priority: Priority) -> Ticket {
+        Ticket {
+            job_id: Uuid::new_v4(),
+            client_id: ClientID(client.to_string()),
+            program_id: ProgramID("sp1".to_string()),
+            priority,
+        }
+    }
+
+    // Queue `ticket` in the background; its label is sent once it has been admitted
+    fn spawn_job(
+        scheduler: &Arc<Scheduler>,
+        ticket: Ticket,
+        label: &'static str,
+        order: &mpsc::UnboundedSender<&'static str>,
+    ) {
+        let scheduler = scheduler.clone();
+        let order = order.clone();
+        tokio::spawn(async move {
+            let _permit = scheduler.acquire(ticket).await;
+            order.send(label).unwrap();
+        });
+    }
+
+    async fn wait_for_queue_len(scheduler: &Scheduler, len: usize) {
+        while scheduler.queued().len() != len {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn admission_order(
+        rx: &mut mpsc::UnboundedReceiver<&'static str>,
+        count: usize,
+    ) -> Vec<&'static str> {
+        let mut order = Vec::new();
+        for _ in 0..count {
+            order.push(rx.recv().await.unwrap());
+        }
+        order
+    }
+
+    #[tokio::test]
+    async fn test_higher_priority_runs_first() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let running = scheduler.acquire(ticket("a", Priority::Normal)).await;
+        spawn_job(&scheduler, ticket("a", Priority::Low), "low", &tx);
+        wait_for_queue_len(&scheduler, 1).await;
+        spawn_job(&scheduler, ticket("a", Priority::Normal), "normal", &tx);
+        wait_for_queue_len(&scheduler, 2).await;
+        spawn_job(&scheduler, ticket("a", Priority::High), "high", &tx);
+        wait_for_queue_len(&scheduler, 3).await;
+        drop(running);
+
+        assert_eq!(
+            admission_order(&mut rx, 3).await,
+            vec!["high", "normal", "low"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fair_share_between_clients() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let running = scheduler
+            .acquire(ticket("backfill", Priority::Normal))
+            .await;
+        for (i, label) in ["backfill-1", "backfill-2", "backfill-3"]
+            .into_iter()
+            .enumerate()
+        {
+            spawn_job(&scheduler, ticket("backfill", Priority::Normal), label, &tx);
+            wait_for_queue_len(&scheduler, i + 1).await;
+        }
+        spawn_job(&scheduler, ticket("tip", Priority::Normal), "tip", &tx);
+        wait_for_queue_len(&scheduler, 4).await;
+
+        // The newest job jumps the backfill because its client has nothing running
+        let queued = scheduler.queued();
+        assert_eq!(queued[0].client_id, ClientID("tip".to_string()));
+        drop(running);
+
+        assert_eq!(
+            admission_order(&mut rx, 4).await,
+            vec!["tip", "backfill-1", "backfill-2", "backfill-3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reprioritize_waiting_job() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let running = scheduler.acquire(ticket("a", Priority::Normal)).await;
+        spawn_job(&scheduler, ticket("a", Priority::Normal), "first", &tx);
+        wait_for_queue_len(&scheduler, 1).await;
+        let second = ticket("a", Priority::Normal);
+        let second_id = second.job_id;
+        spawn_job(&scheduler, second, "second", &tx);
+        wait_for_queue_len(&scheduler, 2).await;
+
+        assert!(scheduler.reprioritize(second_id, Priority::High));
+        assert!(!scheduler.reprioritize(Uuid::new_v4(), Priority::High));
+        assert_eq!(scheduler.queued()[0].job_id, second_id);
+        drop(running);
+
+        assert_eq!(admission_order(&mut rx, 2).await, vec!["second", "first"]);
+    }
+
+    #[tokio::test]
+    async fn test_held_queue_admits_nothing() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        scheduler.hold(true);
+        spawn_job(&scheduler, ticket("a", Priority::Normal), "held", &tx);
+        wait_for_queue_len(&scheduler, 1).await;
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+
+        scheduler.hold(false);
+        assert_eq!(admission_order(&mut rx, 1).await, vec!["held"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_queue() {
+        let scheduler = Arc::new(Scheduler::new(1));
+
+        let running = scheduler.acquire(ticket("a", Priority::Normal)).await;
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(ticket("b", Priority::Normal)).await;
+            })
+        };
+        wait_for_queue_len(&scheduler, 1).await;
+        waiter.abort();
+        let _ = waiter.await;
+
+        assert!(scheduler.queued().is_empty());
+        assert_eq!(scheduler.running(), 1);
+        drop(running);
+        assert_eq!(scheduler.running(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_admits_each_job_once() {
+        let scheduler = Arc::new(Scheduler::new(2));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        scheduler.hold(true);
+        for _ in 0..10_000 {
+            spawn_job(
+                &scheduler,
+                ticket("backfill", Priority::Normal),
+                "backfill",
+                &tx,
+            );
+        }
+        spawn_job(&scheduler, ticket("tip", Priority::Normal), "tip", &tx);
+        wait_for_queue_len(&scheduler, 10_001).await;
+        scheduler.hold(false);
+
+        let order = admission_order(&mut rx, 10_001).await;
+        assert_eq!(order.iter().position(|label| *label == "tip"), Some(1));
+        assert!(scheduler.queued().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_admitted_waiter_dropped_before_its_permit() {
+        let scheduler = Arc::new(Scheduler::new(1));
+
+        let running = scheduler.acquire(ticket("a", Priority::Normal)).await;
+        let mut waiter = Box::pin(scheduler.acquire(ticket("b", Priority::Normal)));
+        let polled = tokio::time::timeout(Duration::ZERO, &mut waiter).await;
+        assert!(polled.is_err());
+        // The job is admitted, but its waiter goes away before it takes the permit
+        drop(running);
+        assert_eq!(scheduler.running(), 1);
+        drop(waiter);
+
+        assert_eq!(scheduler.running(), 0);
+        let _permit = scheduler.acquire(ticket("c", Priority::Normal)).await;
+        assert_eq!(scheduler.running(), 1);
+    }
+}
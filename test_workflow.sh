@@ -5,9 +5,18 @@ set -e
 
 # Configuration
 SERVER_URL="http://localhost:3000"
-PROGRAM_ID="sp1"  # Using the pre-compiled SP1 program
+# JSON file holding the program input, e.g. a ClientInput for the pre-compiled SP1 program.
+# Without it a few raw bytes are sent, which suits programs taking `raw` input,
+# such as the `mock` program of a server started with POOST_BACKENDS=mock.
+INPUT_FILE="${INPUT_FILE:-}"
+if [ -n "$INPUT_FILE" ]; then
+    PROGRAM_ID="${PROGRAM_ID:-sp1}"
+else
+    PROGRAM_ID="${PROGRAM_ID:-mock}"
+fi
 PROOF_FILE="proof_response.json"
 VERIFY_FILE="verify_request.json"
+REQUEST_FILE="program_request.json"
 
 # Helper function to make API calls with error handling
 make_request() {
@@ -57,7 +66,7 @@ make_request() {
             ;;
         "execute")
             # Display execution metrics
-            echo "Execution time: $(jq '.execution_time_milliseconds' <<< "$response")ms"
+            echo "Execution time: $(jq '.execution_time_duration | .secs * 1000 + .nanos / 1000000' <<< "$response")ms"
             echo "Total cycles: $(jq '.total_num_cycles' <<< "$response")"
             ;;
         "verify")
@@ -83,25 +92,21 @@ echo "========================================"
 # Step 1: Get server info
 make_request "GET" "info" "" "Getting server information"
 
-# Step 2: Execute the program with supplied values
-EXECUTE_DATA="{
-    \"program_id\": \"$PROGRAM_ID\",
-    \"input\": {
-        \"value1\": 10,
-        \"value2\": 100
-    }
-}"
-make_request "POST" "execute" "$EXECUTE_DATA" "Executing program"
+# Build the execute and prove request
+if [ -n "$INPUT_FILE" ]; then
+    jq -c --arg program_id "$PROGRAM_ID" --slurpfile input "$INPUT_FILE" \
+        '{program_id: $program_id, input: $input[0]}' -n > "$REQUEST_FILE"
+else
+    jq -c --arg program_id "$PROGRAM_ID" --arg input "$(printf 'hello poost' | base64)" \
+        '{program_id: $program_id, input: $input}' -n > "$REQUEST_FILE"
+fi
+
+# Step 2: Execute the program with the input
+make_request "POST" "execute" "" "Executing program" "$REQUEST_FILE"
 
-# Step 3: Generate proof with supplied values
-PROVE_DATA="{
-    \"program_id\": \"$PROGRAM_ID\",
-    \"input\": {
-        \"value1\": 10,
-        \"value2\": 100
-    }
-}"
-make_request "POST" "prove" "$PROVE_DATA" "Generating proof"
+# Step 3: Generate proof with the same input
+make_request "POST" "prove" "" "Generating proof" "$REQUEST_FILE"
+rm "$REQUEST_FILE"
 
 # Step 4: Verify proof
 # Create a temporary file for the verification request